@@ -65,6 +65,7 @@ impl RemoteClient {
             RpcClient::new_runtime(
                 session::Builder::new()
                     .remote_enclaves(enclaves)
+                    .quote_policy(rak.quote_policy())
                     .local_rak(rak),
                 protocol,
                 KEY_MANAGER_ENDPOINT,
@@ -93,6 +93,8 @@ impl Cache {
                 config.storage.cache_node_capacity,
                 config.storage.cache_value_capacity,
             )
+            .with_max_key_size(config.storage.max_key_size)
+            .with_max_value_size(config.storage.max_value_size)
             .with_root(root)
             .new(Box::new(read_syncer))
     }
@@ -1,7 +1,10 @@
 //! Attestation verification report handling.
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::{
+    io::{Cursor, Read, Seek, SeekFrom},
+    str::FromStr,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use base64;
 use byteorder::{LittleEndian, ReadBytesExt};
 use chrono::prelude::*;
@@ -12,14 +15,18 @@ use rsa::{padding::PaddingScheme, pkcs1::FromRsaPublicKey, Hash, PublicKey, RsaP
 use serde_json;
 use sgx_isa::{AttributesFlags, Report};
 use sha2::{Digest, Sha256};
+use slog::{info, warn};
 use thiserror::Error;
 use x509_parser::prelude::*;
 
-use crate::common::time::{insecure_posix_time, update_insecure_posix_time};
+use crate::common::{
+    logger::get_logger,
+    time::{insecure_posix_time, update_insecure_posix_time},
+};
 
 /// AVR verification error.
 #[derive(Error, Debug)]
-enum AVRError {
+pub enum AVRError {
     #[error("failed to parse report body")]
     MalformedReportBody,
     #[error("report body did not contain timestamp")]
@@ -30,6 +37,10 @@ enum AVRError {
     TimestampOutOfRange,
     #[error("rejecting quote status ({status:?})")]
     QuoteStatusInvalid { status: String },
+    #[error("quote status can not be allowed by policy ({status:?})")]
+    QuoteStatusNotAllowable { status: String },
+    #[error("rejecting advisory ({id:?})")]
+    AdvisoryDenied { id: String },
     #[error("debug enclaves not allowed")]
     DebugEnclave,
     #[error("production enclaves not allowed")]
@@ -50,6 +61,24 @@ enum AVRError {
     MalformedCertificateDER,
     #[error("expired certificate")]
     ExpiredCertificate,
+    #[error("certificate chain trust anchor mismatch")]
+    TrustAnchorMismatch,
+    #[error("certificate chain trust anchor has invalid signature")]
+    TrustAnchorInvalidSignature,
+    #[error("certificate chain trust anchor is not a CA")]
+    TrustAnchorNotCA,
+    #[error("invalid leaf certificate signature")]
+    LeafInvalidSignature,
+    #[error("leaf certificate can't sign")]
+    LeafCannotSign,
+    #[error("leaf certificate missing key usage")]
+    LeafMissingKeyUsage,
+    #[error("invalid certificate public key algorithm")]
+    InvalidPublicKeyAlgorithm,
+    #[error("invalid certificate public key")]
+    InvalidPublicKey,
+    #[error("malformed signature")]
+    MalformedSignature,
     #[error("invalid signature")]
     InvalidSignature,
 }
@@ -156,6 +185,120 @@ impl QuoteBody {
     }
 }
 
+/// Non-OK quote status that may be allowed by a quote policy.
+///
+/// Only statuses indicating a platform which is not fully up to date are represented. Any other
+/// status (e.g., `GROUP_REVOKED`, `KEY_REVOKED` or `SIGNATURE_INVALID`) is always rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuoteStatus {
+    GroupOutOfDate,
+    ConfigurationNeeded,
+    SwHardeningNeeded,
+    ConfigurationAndSwHardeningNeeded,
+}
+
+impl QuoteStatus {
+    /// The status as it appears in the AVR body.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuoteStatus::GroupOutOfDate => "GROUP_OUT_OF_DATE",
+            QuoteStatus::ConfigurationNeeded => "CONFIGURATION_NEEDED",
+            QuoteStatus::SwHardeningNeeded => "SW_HARDENING_NEEDED",
+            QuoteStatus::ConfigurationAndSwHardeningNeeded => {
+                "CONFIGURATION_AND_SW_HARDENING_NEEDED"
+            }
+        }
+    }
+}
+
+impl FromStr for QuoteStatus {
+    type Err = AVRError;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        QUOTE_STATUSES_LAX
+            .iter()
+            .find(|s| s.as_str() == status)
+            .copied()
+            .ok_or_else(|| AVRError::QuoteStatusNotAllowable {
+                status: status.to_owned(),
+            })
+    }
+}
+
+/// Quote statuses that indicate a platform which is not fully up to date, but which may be
+/// accepted depending on the deployment's risk tolerance.
+pub const QUOTE_STATUSES_LAX: &[QuoteStatus] = &[
+    QuoteStatus::GroupOutOfDate,
+    QuoteStatus::ConfigurationNeeded,
+    QuoteStatus::SwHardeningNeeded,
+    QuoteStatus::ConfigurationAndSwHardeningNeeded,
+];
+
+/// Quote status policy used when verifying an AVR.
+///
+/// A quote with the `OK` status is always accepted. Any other status is only accepted if it is
+/// explicitly listed in `allowed_quote_statuses`, which can only contain the statuses from
+/// `QUOTE_STATUSES_LAX`. Regardless of the quote status, the AVR is
+/// rejected if it lists any of the `denied_advisory_ids`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotePolicy {
+    /// Non-OK quote statuses that should be accepted.
+    pub allowed_quote_statuses: Vec<QuoteStatus>,
+    /// Advisory identifiers (e.g., `INTEL-SA-00334`) that cause the AVR to be rejected.
+    pub denied_advisory_ids: Vec<String>,
+}
+
+impl QuotePolicy {
+    /// Policy that only accepts quotes with the `OK` status.
+    pub fn strict() -> Self {
+        Self {
+            allowed_quote_statuses: vec![],
+            denied_advisory_ids: vec![],
+        }
+    }
+
+    /// Policy that also accepts quotes from platforms that are out of date or need
+    /// configuration/software hardening.
+    pub fn lax() -> Self {
+        Self {
+            allowed_quote_statuses: QUOTE_STATUSES_LAX.to_vec(),
+            denied_advisory_ids: vec![],
+        }
+    }
+
+    /// Check whether the given quote status and advisories are acceptable under this policy.
+    pub(crate) fn check(&self, status: &str, advisory_ids: &[String]) -> Result<()> {
+        if status != "OK"
+            && !self
+                .allowed_quote_statuses
+                .iter()
+                .any(|s| s.as_str() == status)
+        {
+            return Err(AVRError::QuoteStatusInvalid {
+                status: status.to_owned(),
+            }
+            .into());
+        }
+        if let Some(id) = advisory_ids
+            .iter()
+            .find(|id| self.denied_advisory_ids.contains(id))
+        {
+            return Err(AVRError::AdvisoryDenied { id: id.to_owned() }.into());
+        }
+        Ok(())
+    }
+}
+
+impl Default for QuotePolicy {
+    fn default() -> Self {
+        if option_env!("OASIS_UNSAFE_LAX_AVR_VERIFY").is_some() {
+            Self::lax()
+        } else {
+            Self::strict()
+        }
+    }
+}
+
 /// Attestation verification report.
 #[derive(Debug, Clone, cbor::Encode, cbor::Decode)]
 pub struct AVR {
@@ -196,6 +339,17 @@ impl ParsedAVR {
         }
     }
 
+    fn advisory_ids(&self) -> Vec<String> {
+        match self.body["advisoryIDs"].as_array() {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| id.as_str())
+                .map(|id| id.to_string())
+                .collect(),
+            None => vec![],
+        }
+    }
+
     fn isv_enclave_quote_body(&self) -> Result<String> {
         match self.body["isvEnclaveQuoteBody"].as_str() {
             Some(quote_body) => Ok(quote_body.to_string()),
@@ -221,10 +375,14 @@ impl ParsedAVR {
     }
 }
 
-/// Verify attestation report.
+/// Verify attestation report using the default quote policy.
 pub fn verify(avr: &AVR) -> Result<AuthenticatedAVR> {
+    verify_with_policy(avr, &QuotePolicy::default())
+}
+
+/// Verify attestation report using the given quote policy.
+pub fn verify_with_policy(avr: &AVR, policy: &QuotePolicy) -> Result<AuthenticatedAVR> {
     let unsafe_skip_avr_verification = option_env!("OASIS_UNSAFE_SKIP_AVR_VERIFY").is_some();
-    let unsafe_lax_avr_verification = option_env!("OASIS_UNSAFE_LAX_AVR_VERIFY").is_some();
 
     // Get the time.
     let timestamp_now = insecure_posix_time();
@@ -251,26 +409,26 @@ pub fn verify(avr: &AVR) -> Result<AuthenticatedAVR> {
     let nonce = avr_body.nonce()?;
 
     let quote_status = avr_body.isv_enclave_quote_status()?;
-    match quote_status.as_str() {
-        "OK" => {}
-        "GROUP_OUT_OF_DATE"
-        | "CONFIGURATION_NEEDED"
-        | "SW_HARDENING_NEEDED"
-        | "CONFIGURATION_AND_SW_HARDENING_NEEDED" => {
-            if !unsafe_lax_avr_verification {
-                return Err(AVRError::QuoteStatusInvalid {
-                    status: quote_status.to_owned(),
-                }
-                .into());
+    let advisory_ids = avr_body.advisory_ids();
+    let logger = get_logger("common/sgx/avr");
+    match policy.check(&quote_status, &advisory_ids) {
+        Ok(_) => {
+            if quote_status != "OK" {
+                info!(logger, "Accepting quote status allowed by policy";
+                    "status" => &quote_status,
+                    "advisory_ids" => ?advisory_ids,
+                );
             }
         }
-        _ => {
-            return Err(AVRError::QuoteStatusInvalid {
-                status: quote_status.to_owned(),
-            }
-            .into());
+        Err(err) => {
+            warn!(logger, "Rejecting quote due to policy";
+                "status" => &quote_status,
+                "advisory_ids" => ?advisory_ids,
+                "err" => %err,
+            );
+            return Err(err);
         }
-    };
+    }
 
     let quote_body = avr_body.isv_enclave_quote_body()?;
     let quote_body = match base64::decode(&quote_body) {
@@ -387,7 +545,7 @@ fn validate_avr_signature(
     // the borrow checker thwarted my attempts to initialize a tuple
     // containing a X509Certificate and Pem via lazy_static.
     if cert_ders[1] != *IAS_TRUST_ANCHOR {
-        return Err(anyhow!("AVR certificate chain trust anchor mismatch"));
+        return Err(AVRError::TrustAnchorMismatch.into());
     }
     let anchor = match parse_x509_certificate(&cert_ders[1]) {
         Ok((_, cert)) => cert,
@@ -400,12 +558,10 @@ fn validate_avr_signature(
     if !check_certificate_rsa_signature(&anchor, &anchor_pk) {
         // The hard-coded cert is self-signed.  This will need to be
         // changed if it ever isn't.
-        return Err(anyhow!(
-            "AVR certificate chain trust anchor has invalid signature"
-        ));
+        return Err(AVRError::TrustAnchorInvalidSignature.into());
     }
     if !anchor.tbs_certificate.is_ca() {
-        return Err(anyhow!("AVR certificate trust anchor is not a CA"));
+        return Err(AVRError::TrustAnchorNotCA.into());
     }
 
     // Attestation Report Signing Certificate (leaf):
@@ -422,7 +578,7 @@ fn validate_avr_signature(
         Err(_) => return Err(AVRError::MalformedCertificateDER.into()),
     };
     if !check_certificate_rsa_signature(&leaf, &anchor_pk) {
-        return Err(anyhow!("invalid leaf certificate signature"));
+        return Err(AVRError::LeafInvalidSignature.into());
     }
 
     if !leaf.validity().is_valid_at(time) {
@@ -431,11 +587,11 @@ fn validate_avr_signature(
     match leaf.tbs_certificate.key_usage() {
         Some(ku) => {
             if !ku.1.digital_signature() {
-                return Err(anyhow!("leaf certificate can't sign"));
+                return Err(AVRError::LeafCannotSign.into());
             }
         }
         None => {
-            return Err(anyhow!("leaf cert missing key usage"));
+            return Err(AVRError::LeafMissingKeyUsage.into());
         }
     }
 
@@ -443,7 +599,10 @@ fn validate_avr_signature(
     let leaf_pk = extract_certificate_rsa_public_key(&leaf)?;
     let padding = PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256));
     let digest = Sha256::new().chain(message).finalize();
-    let signature = base64::decode(signature)?;
+    let signature = match base64::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return Err(AVRError::MalformedSignature.into()),
+    };
     match leaf_pk.verify(padding, &digest, &signature) {
         Ok(_) => Ok(()),
         Err(_) => return Err(AVRError::InvalidSignature.into()),
@@ -453,12 +612,12 @@ fn validate_avr_signature(
 fn extract_certificate_rsa_public_key(cert: &X509Certificate) -> Result<RsaPublicKey> {
     let cert_spki = &cert.tbs_certificate.subject_pki;
     if cert_spki.algorithm.algorithm != OID_PKCS1_RSAENCRYPTION {
-        return Err(anyhow!("invalid certificate public key algorithm"));
+        return Err(AVRError::InvalidPublicKeyAlgorithm.into());
     }
 
     match RsaPublicKey::from_pkcs1_der(cert_spki.subject_public_key.data) {
         Ok(pk) => Ok(pk),
-        Err(err) => return Err(anyhow!("invalid certificate public key: {:?}", err)),
+        Err(_) => return Err(AVRError::InvalidPublicKey.into()),
     }
 }
 
@@ -542,13 +701,19 @@ mod tests {
 
         // Invalid timestamp.
         let result = validate_avr_signature(IAS_CERT_CHAIN, MSG, SIG, 0);
-        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<AVRError>(),
+            Some(AVRError::ExpiredCertificate)
+        ));
 
         // Bad message.
         let bad_msg: &mut [u8] = &mut MSG.to_owned();
         bad_msg[0] ^= 0x23;
         let result = validate_avr_signature(IAS_CERT_CHAIN, bad_msg, SIG, SIG_AT);
-        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<AVRError>(),
+            Some(AVRError::InvalidSignature)
+        ));
 
         // Bad signature.
         let bad_sig = base64::decode(SIG).unwrap();
@@ -556,10 +721,81 @@ mod tests {
         bad_sig[0] ^= 0x42;
         let bad_sig = base64::encode(bad_sig);
         let result = validate_avr_signature(IAS_CERT_CHAIN, MSG, bad_sig.as_bytes(), SIG_AT);
-        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<AVRError>(),
+            Some(AVRError::InvalidSignature)
+        ));
+
+        // Malformed signature.
+        let result = validate_avr_signature(IAS_CERT_CHAIN, MSG, b"not base64!", SIG_AT);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<AVRError>(),
+            Some(AVRError::MalformedSignature)
+        ));
+
+        // Chain with the trust anchor missing.
+        let raw_chain = percent_encoding::percent_decode(IAS_CERT_CHAIN)
+            .decode_utf8()
+            .unwrap();
+        let leaf_end = raw_chain.find("-----END CERTIFICATE-----").unwrap();
+        let leaf_pem = &raw_chain[..leaf_end + "-----END CERTIFICATE-----".len()];
+        let bad_chain = format!("{}\n{}\n", leaf_pem, leaf_pem);
+        let result = validate_avr_signature(bad_chain.as_bytes(), MSG, SIG, SIG_AT);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<AVRError>(),
+            Some(AVRError::TrustAnchorMismatch)
+        ));
 
         // Test timestamp validation while we're at it.
         let timestamp = parse_avr_timestamp("2018-03-30T22:02:26.123456").unwrap();
         assert_eq!(timestamp, SIG_AT as i64);
     }
+
+    #[test]
+    fn test_quote_policy() {
+        let strict = QuotePolicy::strict();
+        assert!(strict.check("OK", &[]).is_ok());
+        assert!(matches!(
+            strict
+                .check("GROUP_OUT_OF_DATE", &[])
+                .unwrap_err()
+                .downcast_ref::<AVRError>(),
+            Some(AVRError::QuoteStatusInvalid { .. })
+        ));
+
+        let lax = QuotePolicy::lax();
+        assert!(lax.check("OK", &[]).is_ok());
+        for status in QUOTE_STATUSES_LAX {
+            assert!(lax.check(status.as_str(), &[]).is_ok());
+            assert_eq!(status.as_str().parse::<QuoteStatus>().unwrap(), *status);
+        }
+        assert!(lax.check("SIGNATURE_INVALID", &[]).is_err());
+
+        // Statuses outside of the lax set can not be allowed by a policy.
+        for status in &["OK", "GROUP_REVOKED", "KEY_REVOKED", "SIGNATURE_INVALID"] {
+            assert!(matches!(
+                status.parse::<QuoteStatus>(),
+                Err(AVRError::QuoteStatusNotAllowable { .. })
+            ));
+        }
+
+        let policy = QuotePolicy {
+            allowed_quote_statuses: vec![QuoteStatus::SwHardeningNeeded],
+            denied_advisory_ids: vec!["INTEL-SA-00334".to_owned()],
+        };
+        assert!(policy
+            .check("SW_HARDENING_NEEDED", &["INTEL-SA-00615".to_owned()])
+            .is_ok());
+        assert!(policy.check("GROUP_OUT_OF_DATE", &[]).is_err());
+        assert!(matches!(
+            policy
+                .check(
+                    "SW_HARDENING_NEEDED",
+                    &["INTEL-SA-00615".to_owned(), "INTEL-SA-00334".to_owned()]
+                )
+                .unwrap_err()
+                .downcast_ref::<AVRError>(),
+            Some(AVRError::AdvisoryDenied { id }) if id == "INTEL-SA-00334"
+        ));
+    }
 }
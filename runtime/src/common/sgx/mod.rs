@@ -3,3 +3,5 @@
 pub mod avr;
 pub mod egetkey;
 pub mod seal;
+
+pub use self::avr::AVRError;
@@ -1,5 +1,8 @@
 //! Runtime configuration.
-use crate::{common::version::Version, consensus::verifier::TrustRoot};
+use crate::{
+    common::{sgx::avr::QuotePolicy, version::Version},
+    consensus::verifier::TrustRoot,
+};
 
 /// Global runtime configuration.
 #[derive(Clone, Debug, Default)]
@@ -10,6 +13,8 @@ pub struct Config {
     pub trust_root: Option<TrustRoot>,
     /// Storage configuration.
     pub storage: Storage,
+    /// Quote policy used when verifying attestation verification reports.
+    pub quote_policy: QuotePolicy,
 }
 
 /// Storage-related configuration.
@@ -21,6 +26,12 @@ pub struct Storage {
     /// The total size, in bytes, of values held by the cache before eviction.
     /// A zero value denotes unlimited capacity.
     pub cache_value_capacity: usize,
+    /// The maximum size, in bytes, of keys that can be inserted into the state tree.
+    /// A zero value denotes the largest key size supported by the tree.
+    pub max_key_size: usize,
+    /// The maximum size, in bytes, of values that can be inserted into the state tree.
+    /// A zero value denotes unlimited size.
+    pub max_value_size: usize,
 }
 
 impl Default for Storage {
@@ -28,6 +39,8 @@ impl Default for Storage {
         Self {
             cache_node_capacity: 100_000,
             cache_value_capacity: 32 * 1024 * 1024, // 32 MiB
+            max_key_size: 0,
+            max_value_size: 0,
         }
     }
 }
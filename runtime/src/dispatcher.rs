@@ -573,6 +573,7 @@ impl Dispatcher {
                 header.round + 1,
             )
             .expect("state commit must succeed");
+        drop(overlay);
 
         txn_dispatcher.finalize(new_state_root);
         cache.commit(header.round + 1, new_state_root);
@@ -134,7 +134,10 @@ impl Demux {
 
             // Create a new session.
             if self.sessions.len() < self.max_concurrent_sessions {
-                let mut session = Builder::new().local_rak(self.rak.clone()).build_responder();
+                let mut session = Builder::new()
+                    .quote_policy(self.rak.quote_policy())
+                    .local_rak(self.rak.clone())
+                    .build_responder();
                 let result = match session.process_data(frame.payload, writer).map(|m| {
                     m.map(|msg| (id, session.session_info(), msg, untrusted_plaintext.clone()))
                 }) {
@@ -50,6 +50,7 @@ pub struct Session {
     local_static_pub: Vec<u8>,
     rak: Option<Arc<RAK>>,
     remote_enclaves: Option<HashSet<avr::EnclaveIdentity>>,
+    quote_policy: avr::QuotePolicy,
     info: Option<Arc<SessionInfo>>,
     state: State,
     buf: Vec<u8>,
@@ -61,11 +62,13 @@ impl Session {
         local_static_pub: Vec<u8>,
         rak: Option<Arc<RAK>>,
         remote_enclaves: Option<HashSet<avr::EnclaveIdentity>>,
+        quote_policy: avr::QuotePolicy,
     ) -> Self {
         Self {
             local_static_pub,
             rak,
             remote_enclaves,
+            quote_policy,
             info: None,
             state: State::Handshake1(handshake_state),
             buf: vec![0u8; 65535],
@@ -208,7 +211,7 @@ impl Session {
         }
 
         let rak_binding: RAKBinding = cbor::from_slice(rak_binding)?;
-        let authenticated_avr = avr::verify(&rak_binding.avr)?;
+        let authenticated_avr = avr::verify_with_policy(&rak_binding.avr, &self.quote_policy)?;
 
         // Verify MRENCLAVE/MRSIGNER.
         if let Some(ref remote_enclaves) = self.remote_enclaves {
@@ -280,6 +283,7 @@ pub struct RAKBinding {
 pub struct Builder {
     rak: Option<Arc<RAK>>,
     remote_enclaves: Option<HashSet<avr::EnclaveIdentity>>,
+    quote_policy: avr::QuotePolicy,
 }
 
 impl Builder {
@@ -288,6 +292,7 @@ impl Builder {
         Self {
             rak: None,
             remote_enclaves: None,
+            quote_policy: avr::QuotePolicy::default(),
         }
     }
 
@@ -302,6 +307,12 @@ impl Builder {
         self
     }
 
+    /// Set the quote policy used when verifying the remote attestation verification report.
+    pub fn quote_policy(mut self, quote_policy: avr::QuotePolicy) -> Self {
+        self.quote_policy = quote_policy;
+        self
+    }
+
     /// Enable RAK binding.
     pub fn local_rak(mut self, rak: Arc<RAK>) -> Self {
         self.rak = Some(rak);
@@ -315,32 +326,75 @@ impl Builder {
         snow::Keypair,
         Option<Arc<RAK>>,
         Option<HashSet<avr::EnclaveIdentity>>,
+        avr::QuotePolicy,
     ) {
         let noise_builder = snow::Builder::new(NOISE_PATTERN.parse().unwrap());
         let rak = self.rak.take();
         let remote_enclaves = self.remote_enclaves.take();
         let keypair = noise_builder.generate_keypair().unwrap();
 
-        (noise_builder, keypair, rak, remote_enclaves)
+        (
+            noise_builder,
+            keypair,
+            rak,
+            remote_enclaves,
+            self.quote_policy,
+        )
     }
 
     /// Build initiator session.
     pub fn build_initiator(self) -> Session {
-        let (builder, keypair, rak, enclaves) = self.build();
+        let (builder, keypair, rak, enclaves, quote_policy) = self.build();
         let session = builder
             .local_private_key(&keypair.private)
             .build_initiator()
             .unwrap();
-        Session::new(session, keypair.public, rak, enclaves)
+        Session::new(session, keypair.public, rak, enclaves, quote_policy)
     }
 
     /// Build responder session.
     pub fn build_responder(self) -> Session {
-        let (builder, keypair, rak, enclaves) = self.build();
+        let (builder, keypair, rak, enclaves, quote_policy) = self.build();
         let session = builder
             .local_private_key(&keypair.private)
             .build_responder()
             .unwrap();
-        Session::new(session, keypair.public, rak, enclaves)
+        Session::new(session, keypair.public, rak, enclaves, quote_policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_policy() {
+        let policy = avr::QuotePolicy {
+            allowed_quote_statuses: vec![avr::QuoteStatus::SwHardeningNeeded],
+            denied_advisory_ids: vec![],
+        };
+
+        // Both ends of the session must verify the remote AVR with the configured policy.
+        for session in vec![
+            Builder::new()
+                .quote_policy(policy.clone())
+                .build_initiator(),
+            Builder::new()
+                .quote_policy(policy.clone())
+                .build_responder(),
+        ] {
+            assert!(session
+                .quote_policy
+                .check("SW_HARDENING_NEEDED", &[])
+                .is_ok());
+            assert!(matches!(
+                session
+                    .quote_policy
+                    .check("GROUP_OUT_OF_DATE", &[])
+                    .unwrap_err()
+                    .downcast_ref::<avr::AVRError>(),
+                Some(avr::AVRError::QuoteStatusInvalid { .. })
+            ));
+        }
     }
 }
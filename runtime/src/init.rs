@@ -23,7 +23,7 @@ pub fn start_runtime(initializer: Box<dyn Initializer>, config: Config) {
     info!(logger, "Runtime is starting");
 
     // Initialize runtime attestation key.
-    let rak = Arc::new(RAK::new());
+    let rak = Arc::new(RAK::with_quote_policy(config.quote_policy.clone()));
 
     // Initialize the dispatcher.
     let dispatcher = Dispatcher::new(initializer, rak.clone());
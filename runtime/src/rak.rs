@@ -50,6 +50,7 @@ enum AVRError {
 }
 
 struct Inner {
+    quote_policy: avr::QuotePolicy,
     private_key: Option<PrivateKey>,
     avr: Option<Arc<avr::AVR>>,
     avr_timestamp: Option<i64>,
@@ -75,8 +76,15 @@ pub struct RAK {
 impl RAK {
     /// Create an uninitialized runtime attestation key instance.
     pub fn new() -> Self {
+        Self::with_quote_policy(avr::QuotePolicy::default())
+    }
+
+    /// Create an uninitialized runtime attestation key instance which verifies
+    /// attestation verification reports using the given quote policy.
+    pub fn with_quote_policy(quote_policy: avr::QuotePolicy) -> Self {
         Self {
             inner: RwLock::new(Inner {
+                quote_policy,
                 private_key: None,
                 avr: None,
                 avr_timestamp: None,
@@ -196,7 +204,7 @@ impl RAK {
         }
         inner.nonce = None;
 
-        let authenticated_avr = avr::verify(&avr)?;
+        let authenticated_avr = avr::verify_with_policy(&avr, &inner.quote_policy)?;
 
         // Verify that the AVR's enclave identity matches our own.
         let enclave_identity = inner
@@ -238,6 +246,12 @@ impl RAK {
         Ok(())
     }
 
+    /// Quote policy used when verifying attestation verification reports.
+    pub fn quote_policy(&self) -> avr::QuotePolicy {
+        let inner = self.inner.read().unwrap();
+        inner.quote_policy.clone()
+    }
+
     /// Public part of RAK.
     ///
     /// This method may return `None` in the case where the enclave is not
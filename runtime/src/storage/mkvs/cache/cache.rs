@@ -6,12 +6,23 @@ use io_context::Context;
 use crate::storage::mkvs::{cache::lru_cache::CacheItemBox, sync::*, tree::*};
 
 /// Statistics about the contents of the cache.
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct CacheStats {
     /// Count of internal nodes held by the cache.
+    ///
+    /// Internal nodes are only counted, their size in memory is not tracked.
     pub internal_node_count: usize,
-    /// Total size of values held by the cache.
+    /// Total size, in bytes, of leaf keys and values held by the cache.
     pub leaf_value_size: usize,
+    /// Number of node dereferences that were served from the cache.
+    ///
+    /// Dereferences of dirty nodes, which are not tracked by the cache until
+    /// they are committed, are not counted.
+    pub hits: u64,
+    /// Number of node dereferences that required a fetch from the read syncer.
+    pub misses: u64,
+    /// Number of nodes, internal and leaf, evicted from the cache to make space for new ones.
+    pub evictions: u64,
 }
 
 /// Used to fetch proofs from a remote tree via the ReadSyncer interface.
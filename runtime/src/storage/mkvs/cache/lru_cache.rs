@@ -4,6 +4,7 @@ use anyhow::{anyhow, Result};
 use intrusive_collections::{intrusive_adapter, LinkedList, LinkedListLink};
 use io_context::Context;
 use thiserror::Error;
+use zeroize::Zeroize;
 
 use crate::storage::mkvs::{cache::*, sync::*, tree::*};
 
@@ -14,6 +15,7 @@ struct RemoveLockedError;
 #[derive(Clone, Default)]
 pub struct CacheItemBox<Item: CacheItem + Default> {
     item: Rc<RefCell<Item>>,
+    size: usize,
     link: LinkedListLink,
 }
 
@@ -59,9 +61,13 @@ where
     fn add(&mut self, val: Rc<RefCell<V>>) {
         let mut val_ref = val.borrow_mut();
         if val_ref.get_cache_extra().is_none() {
-            self.size += val_ref.get_cached_size();
+            // Remember the size the item was accounted with as the item may
+            // change (e.g., become dirty) before it is removed.
+            let size = val_ref.get_cached_size();
+            self.size += size;
             let mut item_box = Box::pin(CacheItemBox {
                 item: val.clone(),
+                size,
                 link: LinkedListLink::new(),
             });
             val_ref.set_cache_extra(NonNull::new(&mut *item_box));
@@ -105,9 +111,8 @@ where
                 match item_cursor.remove() {
                     None => false,
                     Some(item_box) => {
-                        let mut val = item_box.item.borrow_mut();
-                        val.set_cache_extra(None);
-                        self.size -= val.get_cached_size();
+                        item_box.item.borrow_mut().set_cache_extra(None);
+                        self.size -= item_box.size;
                         true
                     }
                 }
@@ -148,6 +153,12 @@ pub struct LRUCache {
 
     lru_leaf: LRUList<NodePointer>,
     lru_internal: LRUList<NodePointer>,
+
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+
+    zeroize_values: bool,
 }
 
 impl LRUCache {
@@ -155,14 +166,17 @@ impl LRUCache {
     ///
     /// * `node_capacity` is the maximum number of internal nodes held by the
     ///   cache before eviction.
-    /// * `value_capacity` is the total size, in bytes, of values held
-    ///   by the cache before eviction.
+    /// * `value_capacity` is the total size, in bytes, of leaf keys and values
+    ///   held by the cache before eviction.
     /// * `read_syncer` is the read syncer used as backing for the cache.
+    /// * `zeroize_values` controls whether leaf keys and values are zeroized
+    ///   when their nodes are removed from the cache.
     pub fn new(
         node_capacity: usize,
         value_capacity: usize,
         read_syncer: Box<dyn ReadSync>,
         root_type: RootType,
+        zeroize_values: bool,
     ) -> Box<LRUCache> {
         Box::new(LRUCache {
             read_syncer: read_syncer,
@@ -178,6 +192,12 @@ impl LRUCache {
 
             lru_leaf: LRUList::new(value_capacity),
             lru_internal: LRUList::new(node_capacity),
+
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+
+            zeroize_values: zeroize_values,
         })
     }
 
@@ -215,6 +235,7 @@ impl LRUCache {
                 let evicted = self
                     .lru_internal
                     .evict_for_val(ptr.clone(), locked_ptr.clone())?;
+                self.evictions += evicted.len() as u64;
                 for node in evicted {
                     self.try_remove_node(node.clone(), locked_ptr.clone())?;
                 }
@@ -224,6 +245,7 @@ impl LRUCache {
                 let evicted = self
                     .lru_leaf
                     .evict_for_val(ptr.clone(), locked_ptr.clone())?;
+                self.evictions += evicted.len() as u64;
                 for node in evicted {
                     self.try_remove_node(node.clone(), locked_ptr.clone())?;
                 }
@@ -298,7 +320,10 @@ impl LRUCache {
                 }
                 NodeKind::Leaf => {
                     self.lru_leaf.remove(top.0.clone());
-                    top.0.borrow_mut().node = None;
+                    let node = top.0.borrow_mut().node.take();
+                    if self.zeroize_values {
+                        zeroize_leaf_node(node);
+                    }
                 }
                 NodeKind::None => {}
             }
@@ -321,17 +346,15 @@ impl LRUCache {
         }
 
         // Commit all children.
-        match classify_noderef!(? ptr.borrow().node) {
-            NodeKind::Internal => {
-                let node_ref = ptr.borrow().get_node();
-                self.commit_merged_node(noderef_as!(node_ref, Internal).left.clone(), &locked_ptr)?;
-                self.commit_merged_node(
-                    noderef_as!(node_ref, Internal).right.clone(),
-                    &locked_ptr,
-                )?;
+        let node_ref = ptr.borrow().node.clone();
+        if let Some(node_ref) = node_ref {
+            let children = match *node_ref.borrow() {
+                NodeBox::Internal(ref n) => vec![n.left.clone(), n.right.clone()],
+                NodeBox::Leaf(_) => vec![],
+            };
+            for child in children {
+                self.commit_merged_node(child, &locked_ptr)?;
             }
-            NodeKind::Leaf => {}
-            NodeKind::None => {}
         }
 
         Ok(())
@@ -347,6 +370,9 @@ impl Cache for LRUCache {
         CacheStats {
             internal_node_count: self.lru_internal.size,
             leaf_value_size: self.lru_leaf.size,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
         }
     }
 
@@ -412,7 +438,7 @@ impl Cache for LRUCache {
         let ptr_ref = ptr;
         let ptr = ptr_ref.borrow();
 
-        self.use_node(ptr_ref.clone());
+        let tracked = self.use_node(ptr_ref.clone());
 
         if let Some(ref node) = &ptr.node {
             let refetch = match *node.borrow() {
@@ -429,6 +455,10 @@ impl Cache for LRUCache {
                 drop(ptr);
                 self.remove_node(ptr_ref.clone());
             } else {
+                // Dirty nodes are not tracked by the cache, so they do not count as hits.
+                if tracked {
+                    self.hits += 1;
+                }
                 return Ok(Some(node.clone()));
             }
         } else {
@@ -440,6 +470,7 @@ impl Cache for LRUCache {
 
         // Node not available locally, fetch from read syncer.
         if let Some(fetcher) = fetcher {
+            self.misses += 1;
             self.remote_sync(ctx, ptr_ref.clone(), fetcher)?;
         } else {
             return Err(anyhow!(
@@ -449,9 +480,7 @@ impl Cache for LRUCache {
 
         let ptr = ptr_ref.borrow();
         if ptr.node.is_none() {
-            return Err(anyhow!(
-                "mkvs: received result did not contain node (or cache too small)"
-            ));
+            return Err(VerifierError::MissingNode.into());
         }
         Ok(ptr.node.clone())
     }
@@ -478,10 +507,11 @@ impl Cache for LRUCache {
         } else if proof.untrusted_root == self.sync_root.hash {
             (self.pending_root.clone(), self.sync_root.hash)
         } else {
-            return Err(anyhow!(
-                "mkvs: got proof for unexpected root ({:?})",
-                proof.untrusted_root
-            ));
+            return Err(VerifierError::UnexpectedRoot {
+                expected: ptr_hash,
+                got: proof.untrusted_root,
+            }
+            .into());
         };
 
         // Verify proof.
@@ -541,3 +571,24 @@ impl Cache for LRUCache {
         self.lru_leaf.mark();
     }
 }
+
+impl Drop for LRUCache {
+    fn drop(&mut self) {
+        if self.zeroize_values {
+            // Make sure that values still held by the cache do not outlive it.
+            self.remove_node(self.pending_root.clone());
+        }
+    }
+}
+
+/// Zeroize the key and value of a removed leaf node.
+///
+/// The node is only zeroized if nothing else holds a reference to it, as
+/// otherwise the contents would change under the remaining owners.
+fn zeroize_leaf_node(node: Option<NodeRef>) {
+    if let Some(node) = node.and_then(|node| Rc::try_unwrap(node).ok()) {
+        if let NodeBox::Leaf(mut n) = node.into_inner() {
+            n.zeroize();
+        }
+    }
+}
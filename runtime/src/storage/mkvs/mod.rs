@@ -19,7 +19,8 @@ pub mod sync;
 #[cfg(test)]
 mod tests;
 
-pub use tree::{Depth, Key, NodeBox, OverlayTree, Root, RootType, Tree};
+pub use cache::CacheStats;
+pub use tree::{Depth, Key, NodeBox, OverlayTree, Root, RootType, Tree, MAX_KEY_SIZE};
 
 /// The type of entry in the log.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -115,9 +116,20 @@ pub trait MKVS {
     /// If the database did have this key present, the value is updated, and the old value is
     /// returned.
     ///
+    /// Panics in case the key or the value exceed the size limits of the store. Use
+    /// [`try_insert`] for keys and values whose size is not otherwise bounded.
+    ///
     /// [`None`]: std::option::Option
+    /// [`try_insert`]: MKVS::try_insert
     fn insert(&mut self, ctx: Context, key: &[u8], value: &[u8]) -> Option<Vec<u8>>;
 
+    /// Update entry with given key, like [`insert`], but fail instead of panicking in case the
+    /// key or the value exceed the size limits of the store. The store is not modified on
+    /// failure.
+    ///
+    /// [`insert`]: MKVS::insert
+    fn try_insert(&mut self, ctx: Context, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>>;
+
     /// Remove entry with given key, returning the value at the key if the key was previously
     /// in the database.
     fn remove(&mut self, ctx: Context, key: &[u8]) -> Option<Vec<u8>>;
@@ -163,6 +175,10 @@ pub trait FallibleMKVS {
     /// in the database.
     fn remove(&mut self, ctx: Context, key: &[u8]) -> Result<Option<Vec<u8>>>;
 
+    /// Check that the given key/value pair is within the size limits of the store, without
+    /// inserting it.
+    fn check_size_limits(&self, key: &[u8], value: &[u8]) -> Result<()>;
+
     /// Populate the in-memory tree with nodes for keys starting with given prefixes.
     fn prefetch_prefixes(&self, ctx: Context, prefixes: &Vec<Prefix>, limit: u16) -> Result<()>;
 
@@ -229,6 +245,112 @@ pub trait Iterator: iter::Iterator<Item = (Vec<u8>, Vec<u8>)> {
     fn next(&mut self);
 }
 
+impl<T: Iterator + ?Sized> Iterator for Box<T> {
+    fn set_prefetch(&mut self, prefetch: usize) {
+        T::set_prefetch(self, prefetch)
+    }
+
+    fn is_valid(&self) -> bool {
+        T::is_valid(self)
+    }
+
+    fn error(&self) -> &Option<Error> {
+        T::error(self)
+    }
+
+    fn rewind(&mut self) {
+        T::rewind(self)
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        T::seek(self, key)
+    }
+
+    fn get_key(&self) -> &Option<Key> {
+        T::get_key(self)
+    }
+
+    fn get_value(&self) -> &Option<Vec<u8>> {
+        T::get_value(self)
+    }
+
+    fn next(&mut self) {
+        Iterator::next(&mut **self)
+    }
+}
+
+/// An iterator adapter that only yields entries with keys in a given range.
+///
+/// The range is half-open: it includes the start key and excludes the end key. This makes
+/// it possible to implement paginated queries by resuming iteration from the key following
+/// the last returned key.
+pub struct RangeIterator<I: Iterator> {
+    inner: I,
+    end: Option<Vec<u8>>,
+}
+
+impl<I: Iterator> RangeIterator<I> {
+    /// Create an iterator over keys in the range `[start, end)`. When `end` is `None`, the
+    /// range is unbounded and iteration continues until the last key in the tree.
+    pub fn new(mut inner: I, start: &[u8], end: Option<&[u8]>) -> Self {
+        inner.seek(start);
+
+        Self {
+            inner,
+            end: end.map(|end| end.to_vec()),
+        }
+    }
+
+    /// Create an iterator over keys starting with the given prefix.
+    pub fn with_prefix(inner: I, prefix: &[u8]) -> Self {
+        let end = prefix_end(prefix);
+        Self::new(inner, prefix, end.as_deref())
+    }
+
+    /// Sets the number of next elements to prefetch.
+    pub fn set_prefetch(&mut self, prefetch: usize) {
+        self.inner.set_prefetch(prefetch)
+    }
+
+    /// Return the error that occurred during iteration if any.
+    pub fn error(&self) -> &Option<Error> {
+        self.inner.error()
+    }
+
+    /// Return the wrapped iterator.
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I: Iterator> iter::Iterator for RangeIterator<I> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.inner.get_key(), &self.end) {
+            (None, _) => return None,
+            (Some(key), Some(end)) if key >= end => return None,
+            _ => {}
+        }
+
+        iter::Iterator::next(&mut self.inner)
+    }
+}
+
+/// Return the smallest key that is larger than all keys starting with the given prefix or
+/// `None` in case there is no such key (e.g., the prefix is empty or consists only of 0xFF
+/// bytes).
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
 impl<T: MKVS + ?Sized> MKVS for &mut T {
     fn get(&self, ctx: Context, key: &[u8]) -> Option<Vec<u8>> {
         T::get(self, ctx, key)
@@ -242,6 +364,10 @@ impl<T: MKVS + ?Sized> MKVS for &mut T {
         T::insert(self, ctx, key, value)
     }
 
+    fn try_insert(&mut self, ctx: Context, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        T::try_insert(self, ctx, key, value)
+    }
+
     fn remove(&mut self, ctx: Context, key: &[u8]) -> Option<Vec<u8>> {
         T::remove(self, ctx, key)
     }
@@ -281,6 +407,10 @@ impl<T: FallibleMKVS + ?Sized> FallibleMKVS for &mut T {
         T::remove(self, ctx, key)
     }
 
+    fn check_size_limits(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        T::check_size_limits(self, key, value)
+    }
+
     fn prefetch_prefixes(&self, ctx: Context, prefixes: &Vec<Prefix>, limit: u16) -> Result<()> {
         T::prefetch_prefixes(self, ctx, prefixes, limit)
     }
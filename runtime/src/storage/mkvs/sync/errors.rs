@@ -1,7 +1,48 @@
 use thiserror::Error;
 
+use crate::common::crypto::hash::Hash;
+
 #[derive(Error, Debug)]
 pub enum SyncerError {
     #[error("mkvs: method not supported")]
     Unsupported,
+    #[error("mkvs: root is dirty")]
+    DirtyRoot,
+}
+
+/// Errors returned when verifying data received from an untrusted read syncer.
+#[derive(Error, Debug)]
+pub enum VerifierError {
+    #[error("verifier: got proof for unexpected root (expected: {expected:?} got {got:?})")]
+    UnexpectedRoot { expected: Hash, got: Hash },
+    #[error("verifier: bad root (expected: {expected:?} got {got:?})")]
+    BadRoot { expected: Hash, got: Hash },
+    #[error("verifier: malformed proof")]
+    MalformedProof,
+    #[error("verifier: malformed hash entry")]
+    MalformedHashEntry,
+    #[error("verifier: unexpected entry in proof ({0:?})")]
+    UnexpectedEntry(u8),
+    #[error("verifier: empty proof")]
+    EmptyProof,
+    #[error("verifier: proof does not include path to key")]
+    MissingPath,
+    #[error("verifier: received result did not contain node (or cache too small)")]
+    MissingNode,
+    #[error("verifier: hash mismatch during merge (expected: {expected:?} got: {got:?})")]
+    MergeHashMismatch { expected: Hash, got: Hash },
+}
+
+impl VerifierError {
+    /// Whether the error indicates that the received data is incomplete, as opposed to
+    /// the data being inconsistent with the expected root (e.g., forged or corrupted).
+    ///
+    /// An empty proof is not considered missing data, as a syncer can never legitimately
+    /// return one for the requested root.
+    pub fn is_missing(&self) -> bool {
+        matches!(
+            self,
+            VerifierError::MissingPath | VerifierError::MissingNode
+        )
+    }
 }
@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Result};
 
-use crate::storage::mkvs::tree::*;
+use crate::storage::mkvs::{sync::VerifierError, tree::*};
 
 /// Merges a previously verified subtree with an existing tree.
 pub fn merge_verified_subtree(
@@ -27,11 +27,11 @@ pub fn merge_verified_subtree(
     // If the destination pointer is clean, sanity check that we are
     // merging correct nodes.
     if dst.hash != subtree.hash {
-        return Err(anyhow!(
-            "merger: hash mismatch during merge (expected: {:?} got: {:?})",
-            dst.hash,
-            subtree.hash,
-        ));
+        return Err(VerifierError::MergeHashMismatch {
+            expected: dst.hash,
+            got: subtree.hash,
+        }
+        .into());
     }
 
     // If the subtree node is nil, there is nothing more to merge.
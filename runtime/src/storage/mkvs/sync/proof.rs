@@ -1,12 +1,15 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use arbitrary::Arbitrary;
 use io_context::Context;
 
 use crate::{
     common::crypto::hash::Hash,
-    storage::mkvs::{marshal::Marshal, tree::*},
+    storage::mkvs::{marshal::Marshal, sync::VerifierError, tree::*},
 };
 
 /// Proof entry type for full nodes.
@@ -62,6 +65,129 @@ pub struct Proof {
     pub entries: Vec<Option<RawProofEntry>>,
 }
 
+struct ProofNode {
+    serialized: Vec<u8>,
+    children: Vec<Hash>,
+}
+
+/// A Merkle proof builder.
+pub struct ProofBuilder {
+    root: Hash,
+    subtree: Hash,
+    included: HashMap<Hash, ProofNode>,
+    size: u64,
+}
+
+impl ProofBuilder {
+    /// Create a new Merkle proof builder for the given root.
+    pub fn new(root: Hash, subtree: Hash) -> Self {
+        Self {
+            root,
+            subtree,
+            included: HashMap::new(),
+            size: 0,
+        }
+    }
+
+    /// Add a node to the set of included nodes.
+    ///
+    /// The node must be clean, otherwise `TreeError::DirtyNode` is returned.
+    pub fn include(&mut self, node: &NodeBox) -> Result<()> {
+        if !node.is_clean() {
+            return Err(TreeError::DirtyNode.into());
+        }
+
+        // If node is already included, skip it.
+        let hash = node.get_hash();
+        if self.included.contains_key(&hash) {
+            return Ok(());
+        }
+
+        // Node is available, serialize it.
+        let serialized = node.compact_marshal_binary()?;
+
+        // For internal nodes, also add any children. The leaf node is always
+        // included with the internal node.
+        let children = match node {
+            NodeBox::Internal(ref n) => vec![n.left.borrow().hash, n.right.borrow().hash],
+            NodeBox::Leaf(_) => vec![],
+        };
+
+        self.size += 1 + serialized.len() as u64;
+        self.included.insert(
+            hash,
+            ProofNode {
+                serialized,
+                children,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Return true if the subtree root node has already been included.
+    pub fn has_subtree_root(&self) -> bool {
+        self.included.contains_key(&self.subtree)
+    }
+
+    /// Return the subtree root hash for this proof.
+    pub fn get_subtree_root(&self) -> Hash {
+        self.subtree
+    }
+
+    /// Return the current size of this proof.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Build the proof.
+    pub fn build(&self, _ctx: Context) -> Result<Proof> {
+        let mut proof = Proof::default();
+        proof.untrusted_root = if self.has_subtree_root() {
+            // A partial proof for the subtree is available, include that.
+            self.subtree
+        } else {
+            // No partial proof available, we need to use the tree root.
+            self.root
+        };
+
+        self._build(&mut proof, proof.untrusted_root);
+
+        Ok(proof)
+    }
+
+    fn _build(&self, proof: &mut Proof, hash: Hash) {
+        if hash.is_empty() {
+            // Append nil for empty nodes.
+            proof.entries.push(None);
+            return;
+        }
+
+        let node = match self.included.get(&hash) {
+            Some(node) => node,
+            None => {
+                // Node is not included in this proof, just add hash of subtree.
+                let mut entry = Vec::with_capacity(1 + Hash::len());
+                entry.push(PROOF_ENTRY_HASH);
+                entry.extend_from_slice(hash.as_ref());
+                proof.entries.push(Some(entry.into()));
+                return;
+            }
+        };
+
+        // Pre-order traversal, add visited node.
+        let mut entry = Vec::with_capacity(1 + node.serialized.len());
+        entry.push(PROOF_ENTRY_FULL);
+        entry.extend_from_slice(&node.serialized);
+        proof.entries.push(Some(entry.into()));
+
+        // And then add any children.
+        for child_hash in &node.children {
+            self._build(proof, *child_hash);
+        }
+    }
+}
+
 /// A proof verifier enables verifying proofs returned by the ReadSyncer API.
 pub struct ProofVerifier;
 
@@ -72,46 +198,127 @@ impl ProofVerifier {
         // Sanity check that the proof is for the correct root (as otherwise it
         // makes no sense to verify the proof).
         if proof.untrusted_root != root {
-            return Err(anyhow!(
-                "verifier: got proof for unexpected root (expected: {:?} got {:?})",
-                root,
-                proof.untrusted_root,
-            ));
+            return Err(VerifierError::UnexpectedRoot {
+                expected: root,
+                got: proof.untrusted_root,
+            }
+            .into());
         }
         if proof.entries.is_empty() {
-            return Err(anyhow!("verifier: empty proof"));
+            return Err(VerifierError::EmptyProof.into());
         }
 
         let (_, root_node) = self._verify_proof(proof, 0)?;
         let root_hash = root_node.borrow().hash;
         if root_hash != root {
-            return Err(anyhow!(
-                "verifier: bad root (expected: {:?} got {:?})",
-                root,
-                root_hash,
-            ));
+            return Err(VerifierError::BadRoot {
+                expected: root,
+                got: root_hash,
+            }
+            .into());
         }
 
         Ok(root_node)
     }
 
+    /// Verify a proof for the given key and return the value stored under the key, or `None`
+    /// in case the proof shows that the key does not exist under the given root.
+    ///
+    /// This fails if the proof does not include all the nodes on the path to the key.
+    pub fn verify_proof_for_key(
+        &self,
+        ctx: Context,
+        root: Hash,
+        proof: &Proof,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        check_key_length(key)?;
+        let root_node = self.verify_proof(ctx, root, proof)?;
+        self._lookup(root_node, 0, &key.to_vec())
+    }
+
+    /// Verify a proof for multiple keys and return the values stored under the keys, in the
+    /// same order as the keys.
+    ///
+    /// This fails if the proof does not include all the nodes on the paths to all keys.
+    pub fn verify_proof_for_keys(
+        &self,
+        ctx: Context,
+        root: Hash,
+        proof: &Proof,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        for key in keys {
+            check_key_length(key)?;
+        }
+        let root_node = self.verify_proof(ctx, root, proof)?;
+        keys.iter()
+            .map(|key| self._lookup(root_node.clone(), 0, &key.to_vec()))
+            .collect()
+    }
+
+    fn _lookup(&self, ptr: NodePtrRef, bit_depth: Depth, key: &Key) -> Result<Option<Value>> {
+        let ptr = ptr.borrow();
+        if ptr.is_null() {
+            // Reached a nil node, there is nothing here.
+            return Ok(None);
+        }
+        let node_ref = match ptr.node {
+            Some(ref node_ref) => node_ref.clone(),
+            None => return Err(VerifierError::MissingPath.into()),
+        };
+
+        let node = node_ref.borrow();
+        match *node {
+            NodeBox::Internal(ref n) => {
+                let bit_length = bit_depth + n.label_bit_length;
+
+                // Does lookup key end here? Look into the leaf node.
+                if key.bit_length() == bit_length {
+                    return self._lookup(n.leaf_node.clone(), bit_length, key);
+                }
+
+                // Lookup key is too short for the current label. It's not stored.
+                if key.bit_length() < bit_length {
+                    return Ok(None);
+                }
+
+                // Continue recursively based on a bit value.
+                if key.get_bit(bit_length)? {
+                    self._lookup(n.right.clone(), bit_length, key)
+                } else {
+                    self._lookup(n.left.clone(), bit_length, key)
+                }
+            }
+            NodeBox::Leaf(ref n) => {
+                // Reached a leaf node, check if key matches.
+                if n.key == *key {
+                    Ok(Some(n.value.clone()))
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+
     fn _verify_proof(&self, proof: &Proof, idx: usize) -> Result<(usize, NodePtrRef)> {
         if idx >= proof.entries.len() {
-            return Err(anyhow!("verifier: malformed proof"));
+            return Err(VerifierError::MalformedProof.into());
         }
         let entry = match &proof.entries[idx] {
             Some(entry) => entry.as_ref(),
             None => return Ok((idx + 1, NodePointer::null_ptr())),
         };
         if entry.is_empty() {
-            return Err(anyhow!("verifier: malformed proof"));
+            return Err(VerifierError::MalformedProof.into());
         }
 
         match entry[0] {
             PROOF_ENTRY_FULL => {
                 // Full node.
                 let mut node = NodeBox::default();
-                node.unmarshal_binary(&entry[1..])?;
+                node.unmarshal_binary(&entry[1..])
+                    .map_err(|_| VerifierError::MalformedProof)?;
 
                 // For internal nodes, also decode children.
                 let mut pos = idx + 1;
@@ -135,15 +342,12 @@ impl ProofVerifier {
                 // Hash of a node.
                 let entry = &entry[1..];
                 if entry.len() != Hash::len() {
-                    return Err(anyhow!("verifier: malformed hash entry"));
+                    return Err(VerifierError::MalformedHashEntry.into());
                 }
 
                 Ok((idx + 1, NodePointer::hash_ptr(entry.into())))
             }
-            entry_type => Err(anyhow!(
-                "verifier: unexpected entry in proof ({:?})",
-                entry_type
-            )),
+            entry_type => Err(VerifierError::UnexpectedEntry(entry_type).into()),
         }
     }
 }
@@ -155,6 +359,10 @@ mod test {
 
     use super::*;
 
+    fn verifier_error(result: Result<NodePtrRef>) -> Option<VerifierError> {
+        result.err().and_then(|err| err.downcast().ok())
+    }
+
     #[test]
     fn test_proof() {
         // Test vector generated by Go.
@@ -182,7 +390,7 @@ uO/mFPzJZey4liX5fxf4fwcQRhM=",
         let empty_proof = Proof::default();
         let result = pv.verify_proof(Context::background(), root_hash, &empty_proof);
         assert!(
-            result.is_err(),
+            matches!(verifier_error(result), Some(VerifierError::EmptyProof)),
             "verify proof should fail with an empty proof"
         );
 
@@ -190,7 +398,10 @@ uO/mFPzJZey4liX5fxf4fwcQRhM=",
         let bogus_hash = Hash::digest_bytes(b"i am a bogus hash");
         let result = pv.verify_proof(Context::background(), bogus_hash, &proof);
         assert!(
-            result.is_err(),
+            matches!(
+                verifier_error(result),
+                Some(VerifierError::UnexpectedRoot { .. })
+            ),
             "verify proof should fail with a proof for a different root"
         );
 
@@ -199,7 +410,7 @@ uO/mFPzJZey4liX5fxf4fwcQRhM=",
         corrupted.entries[4].as_mut().unwrap()[10] = 0x00;
         let result = pv.verify_proof(Context::background(), root_hash, &corrupted);
         assert!(
-            result.is_err(),
+            matches!(verifier_error(result), Some(VerifierError::BadRoot { .. })),
             "verify proof should fail with invalid proof"
         );
 
@@ -208,7 +419,7 @@ uO/mFPzJZey4liX5fxf4fwcQRhM=",
         corrupted.entries[0].as_mut().unwrap().truncate(3);
         let result = pv.verify_proof(Context::background(), root_hash, &corrupted);
         assert!(
-            result.is_err(),
+            matches!(verifier_error(result), Some(VerifierError::MalformedProof)),
             "verify proof should fail with invalid proof"
         );
 
@@ -217,7 +428,10 @@ uO/mFPzJZey4liX5fxf4fwcQRhM=",
         corrupted.entries[2].as_mut().unwrap().truncate(3);
         let result = pv.verify_proof(Context::background(), root_hash, &corrupted);
         assert!(
-            result.is_err(),
+            matches!(
+                verifier_error(result),
+                Some(VerifierError::MalformedHashEntry)
+            ),
             "verify proof should fail with invalid proof"
         );
 
@@ -226,7 +440,10 @@ uO/mFPzJZey4liX5fxf4fwcQRhM=",
         corrupted.entries[3].as_mut().unwrap()[0] = 0xaa;
         let result = pv.verify_proof(Context::background(), root_hash, &corrupted);
         assert!(
-            result.is_err(),
+            matches!(
+                verifier_error(result),
+                Some(VerifierError::UnexpectedEntry(0xaa))
+            ),
             "verify proof should fail with invalid proof"
         );
 
@@ -235,8 +452,25 @@ uO/mFPzJZey4liX5fxf4fwcQRhM=",
         corrupted.entries.truncate(3);
         let result = pv.verify_proof(Context::background(), root_hash, &corrupted);
         assert!(
-            result.is_err(),
+            matches!(verifier_error(result), Some(VerifierError::MalformedProof)),
             "verify proof should fail with invalid proof"
         );
     }
+
+    #[test]
+    fn test_proof_builder_dirty_node() {
+        let mut pb = ProofBuilder::new(Hash::empty_hash(), Hash::empty_hash());
+        let node = NodeBox::Leaf(LeafNode {
+            clean: false,
+            key: b"foo".to_vec(),
+            value: b"bar".to_vec(),
+            ..Default::default()
+        });
+        let err = pb.include(&node).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TreeError>(),
+            Some(TreeError::DirtyNode)
+        ));
+        assert_eq!(pb.size(), 0);
+    }
 }
@@ -57,3 +57,32 @@ fn test_nil_pointers() {
         .insert(Context::background(), b"insert", b"key")
         .expect("insert");
 }
+
+#[test]
+fn test_merge_hash_mismatch() {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::common::crypto::hash::Hash;
+
+    let make_ptr = |hash: Hash| {
+        Rc::new(RefCell::new(NodePointer {
+            clean: true,
+            hash,
+            ..Default::default()
+        }))
+    };
+    let dst = make_ptr(Hash::digest_bytes(b"expected"));
+    let subtree = make_ptr(Hash::digest_bytes(b"forged"));
+
+    let err = merge_verified_subtree(dst, subtree, &mut Vec::new())
+        .expect_err("merge should fail on hash mismatch");
+    let err = err
+        .downcast_ref::<VerifierError>()
+        .expect("merge should fail with a verifier error");
+    assert!(matches!(err, VerifierError::MergeHashMismatch { .. }));
+    assert!(!err.is_missing(), "hash mismatch is not missing data");
+
+    assert!(VerifierError::MissingNode.is_missing());
+    assert!(VerifierError::MissingPath.is_missing());
+    assert!(!VerifierError::EmptyProof.is_missing());
+}
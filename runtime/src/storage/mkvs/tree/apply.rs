@@ -0,0 +1,238 @@
+use std::{rc::Rc, sync::Arc};
+
+use anyhow::Result;
+use io_context::Context;
+use zeroize::Zeroize;
+
+use crate::storage::mkvs::{cache::*, tree::*, LogEntry};
+
+use super::{lookup::FetcherSyncGet, tree::check_key_length};
+
+impl Tree {
+    /// Apply a batch of inserts and removals to the tree.
+    ///
+    /// The operations are sorted by key and applied in a single traversal of
+    /// the tree. At each internal node the batch is split between the node's
+    /// leaf and its two subtrees, so every node on the paths shared by several
+    /// keys is only visited once. If the same key is updated more than once,
+    /// only the last operation on it is applied.
+    ///
+    /// All keys and values are checked against the tree's limits before any
+    /// update is made. The updates are not committed.
+    pub fn apply(&mut self, ctx: Context, ops: &[LogEntry]) -> Result<()> {
+        for op in ops {
+            match op.value {
+                Some(ref value) => self.check_size_limits(&op.key, value)?,
+                None => check_key_length(&op.key)?,
+            }
+        }
+
+        self.apply_batch(ctx, ops)
+    }
+
+    /// Apply a batch of inserts and removals to the tree, only checking that
+    /// all keys fit within `MAX_KEY_SIZE`.
+    ///
+    /// The configured key and value size limits are not enforced, so this is
+    /// only suitable for replaying updates that were already accepted elsewhere.
+    pub(super) fn apply_batch(&mut self, ctx: Context, ops: &[LogEntry]) -> Result<()> {
+        for op in ops {
+            check_key_length(&op.key)?;
+        }
+
+        let ctx = ctx.freeze();
+
+        // Stable sort to preserve the order of operations on the same key, then
+        // only keep the last operation on each key.
+        let mut sorted: Vec<&LogEntry> = ops.iter().collect();
+        sorted.sort_by(|a, b| a.key.cmp(&b.key));
+        let mut batch: Vec<&LogEntry> = Vec::with_capacity(sorted.len());
+        for op in sorted {
+            match batch.last_mut() {
+                Some(last) if last.key == op.key => *last = op,
+                _ => batch.push(op),
+            }
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let pending_root = self.cache.borrow().get_pending_root();
+
+        // Remember where the paths from root to target nodes end (will end).
+        self.cache.borrow_mut().mark_position();
+
+        let new_root = self._apply(&ctx, pending_root, 0, &batch, 0)?;
+        self.cache.borrow_mut().set_pending_root(new_root);
+
+        Ok(())
+    }
+
+    fn _apply(
+        &mut self,
+        ctx: &Arc<Context>,
+        ptr: NodePtrRef,
+        bit_depth: Depth,
+        ops: &[&LogEntry],
+        depth: Depth,
+    ) -> Result<NodePtrRef> {
+        let mut ptr = ptr;
+        let mut ops: Vec<&LogEntry> = ops.to_vec();
+        let node_ref = loop {
+            match ops.as_slice() {
+                [] => return Ok(ptr),
+                [op] => return self._apply_one(ctx, ptr, bit_depth, op, depth),
+                _ => {}
+            }
+
+            let node_ref = self.cache.borrow_mut().deref_node_ptr(
+                ctx,
+                ptr.clone(),
+                Some(FetcherSyncGet::new(&ops[0].key, true)),
+            )?;
+
+            // The batch can only be split at an internal node whose label is followed by
+            // all keys in the batch. Otherwise apply the first operation that does not fit
+            // (e.g., one that splits the node) on its own and try again with the rest.
+            let pending = match node_ref {
+                Some(ref node_ref) => match *node_ref.borrow() {
+                    NodeBox::Internal(ref n) => ops
+                        .iter()
+                        .position(|op| !follows_label(n, bit_depth, &op.key)),
+                    NodeBox::Leaf(_) => Some(0),
+                },
+                None => Some(0),
+            };
+            match pending {
+                Some(idx) => {
+                    let op = ops.remove(idx);
+                    ptr = self._apply_one(ctx, ptr, bit_depth, op, depth)?;
+                }
+                None => break node_ref.unwrap(),
+            }
+        };
+
+        // Split the batch between the leaf node and the two subtrees. As the batch is
+        // sorted, the key ending at this node (if any) comes first, followed by the keys
+        // continuing to the left and then by the keys continuing to the right.
+        let bit_length = bit_depth + noderef_as!(node_ref, Internal).label_bit_length;
+        let leaf_end = ops
+            .iter()
+            .take_while(|op| op.key.bit_length() == bit_length)
+            .count();
+        let mut right_start = ops.len();
+        for (idx, op) in ops.iter().enumerate().skip(leaf_end) {
+            if op.key.get_bit(bit_length)? {
+                right_start = idx;
+                break;
+            }
+        }
+
+        let old_leaf_node = noderef_as!(node_ref, Internal).leaf_node.clone();
+        let old_left = noderef_as!(node_ref, Internal).left.clone();
+        let old_right = noderef_as!(node_ref, Internal).right.clone();
+        let leaf_node = self._apply(
+            ctx,
+            old_leaf_node.clone(),
+            bit_length,
+            &ops[..leaf_end],
+            depth,
+        )?;
+        let left = self._apply(
+            ctx,
+            old_left.clone(),
+            bit_length,
+            &ops[leaf_end..right_start],
+            depth + 1,
+        )?;
+        let right = self._apply(
+            ctx,
+            old_right.clone(),
+            bit_length,
+            &ops[right_start..],
+            depth + 1,
+        )?;
+
+        let changed = child_changed(&old_leaf_node, &leaf_node)
+            || child_changed(&old_left, &left)
+            || child_changed(&old_right, &right);
+
+        let child_removed = child_nulled(&old_leaf_node, &leaf_node)
+            || child_nulled(&old_left, &left)
+            || child_nulled(&old_right, &right);
+
+        noderef_as_mut!(node_ref, Internal).leaf_node = leaf_node;
+        noderef_as_mut!(node_ref, Internal).left = left;
+        noderef_as_mut!(node_ref, Internal).right = right;
+
+        // Removals may have left the node with a single child, in which case it needs to be
+        // collapsed just like after a single removal. Inserts never do, so avoid fetching
+        // the siblings needed for the check.
+        if child_removed || ops.iter().any(|op| op.value.is_none()) {
+            let (new_ptr, _) = self.collapse_internal(ctx, ptr, node_ref, &ops[0].key, changed)?;
+            return Ok(new_ptr);
+        }
+
+        if changed {
+            noderef_as_mut!(node_ref, Internal).clean = false;
+            ptr.borrow_mut().clean = false;
+            // No longer eligible for eviction as it is dirty.
+            self.cache
+                .borrow_mut()
+                .rollback_node(ptr.clone(), NodeKind::Internal);
+        }
+        Ok(ptr)
+    }
+
+    fn _apply_one(
+        &mut self,
+        ctx: &Arc<Context>,
+        ptr: NodePtrRef,
+        bit_depth: Depth,
+        op: &LogEntry,
+        depth: Depth,
+    ) -> Result<NodePtrRef> {
+        let (new_ptr, old_val) = match op.value {
+            Some(ref value) => self._insert(ctx, ptr, bit_depth, &op.key, value.clone(), depth)?,
+            None => {
+                let (new_ptr, _, old_val) = self._remove(ctx, ptr, bit_depth, &op.key, depth)?;
+                (new_ptr, old_val)
+            }
+        };
+
+        // Previous values are not returned from a batch, so make sure they do not linger.
+        if let Some(mut old_val) = old_val {
+            if self.zeroize_values {
+                old_val.zeroize();
+            }
+        }
+        Ok(new_ptr)
+    }
+}
+
+/// Whether the given key continues through the label of an internal node at `bit_depth`.
+fn follows_label(n: &InternalNode, bit_depth: Depth, key: &Key) -> bool {
+    let key_length = key.bit_length();
+    if key_length < bit_depth + n.label_bit_length {
+        return false;
+    }
+    let key_remainder = match key.split(bit_depth, key_length) {
+        Ok((_, key_remainder)) => key_remainder,
+        Err(_) => return false,
+    };
+    key_remainder.common_prefix_len(key_length - bit_depth, &n.label, n.label_bit_length)
+        == n.label_bit_length
+}
+
+/// Whether updating a child pointer from `old` to `new` removed the subtree.
+fn child_nulled(old: &NodePtrRef, new: &NodePtrRef) -> bool {
+    !old.borrow().is_null() && new.borrow().is_null()
+}
+
+/// Whether updating a child pointer from `old` to `new` changed the subtree.
+fn child_changed(old: &NodePtrRef, new: &NodePtrRef) -> bool {
+    if !new.borrow().clean {
+        return true;
+    }
+    !(Rc::ptr_eq(old, new) || (old.borrow().is_null() && new.borrow().is_null()))
+}
@@ -5,18 +5,59 @@ use io_context::Context;
 
 use crate::{
     common::{crypto::hash::Hash, namespace::Namespace},
-    storage::mkvs::{cache::*, tree::*},
+    storage::mkvs::{cache::*, tree::*, WriteLog},
 };
 
 impl Tree {
     /// Commit tree updates to the underlying database and return
-    /// the write log and new merkle root.
+    /// the new merkle root.
+    ///
+    /// Use an `OverlayTree` on top of the tree to also obtain the write log.
     pub fn commit(&mut self, ctx: Context, namespace: Namespace, version: u64) -> Result<Hash> {
+        self.commit_with_check(ctx, namespace, version, None)
+    }
+
+    /// Commit tree updates to the underlying database, making sure that the
+    /// resulting merkle root matches the given known root.
+    ///
+    /// In case of a mismatch, `TreeError::KnownRootMismatch` is returned and
+    /// the updates remain uncommitted.
+    pub fn commit_known(&mut self, ctx: Context, root: Root) -> Result<()> {
+        self.commit_with_check(ctx, root.namespace, root.version, Some(root.hash))?;
+        Ok(())
+    }
+
+    /// Apply all updates from the given write log to the tree.
+    ///
+    /// The write log is expected to come from an already accepted update, so
+    /// only the structural key length limit is enforced and not the tree's
+    /// configured size limits.
+    ///
+    /// The updates are not committed. Use `commit_known` to commit them and
+    /// verify the resulting root against an expected one.
+    pub fn apply_write_log(&mut self, ctx: Context, write_log: &WriteLog) -> Result<()> {
+        self.apply_batch(ctx, write_log)
+    }
+
+    fn commit_with_check(
+        &mut self,
+        ctx: Context,
+        namespace: Namespace,
+        version: u64,
+        expected_hash: Option<Hash>,
+    ) -> Result<Hash> {
         let ctx = ctx.freeze();
         let mut update_list: UpdateList<LRUCache> = UpdateList::new();
         let pending_root = self.cache.borrow().get_pending_root();
         let new_hash = _commit(&ctx, pending_root.clone(), &mut update_list)?;
 
+        // Perform pre-commit validation if configured.
+        if let Some(expected_hash) = expected_hash {
+            if new_hash != expected_hash {
+                return Err(TreeError::KnownRootMismatch.into());
+            }
+        }
+
         update_list.commit(&mut self.cache.borrow_mut());
 
         self.cache.borrow_mut().set_sync_root(Root {
@@ -44,7 +85,7 @@ pub fn _commit<C: Cache>(
             ptr.borrow_mut().hash = Hash::empty_hash();
         }
         NodeKind::Internal => {
-            let some_node_ref = ptr.borrow().get_node();
+            let some_node_ref = ptr.borrow().get_node()?;
             if some_node_ref.borrow().is_clean() {
                 ptr.borrow_mut().hash = some_node_ref.borrow().get_hash();
             } else {
@@ -66,7 +107,7 @@ pub fn _commit<C: Cache>(
             }
         }
         NodeKind::Leaf => {
-            let node_ref = ptr.borrow().get_node();
+            let node_ref = ptr.borrow().get_node()?;
             if node_ref.borrow().is_clean() {
                 ptr.borrow_mut().hash = node_ref.borrow().get_hash();
             } else {
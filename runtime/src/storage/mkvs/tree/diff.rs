@@ -0,0 +1,359 @@
+//! Tree diff.
+use std::sync::Arc;
+
+use anyhow::Result;
+use io_context::Context;
+
+use crate::storage::mkvs::{cache::*, sync::*, tree::*};
+
+use super::iterator::FetcherSyncIterate;
+
+/// Number of nodes to prefetch when a node needs to be fetched during a diff.
+const DIFF_PREFETCH: usize = 100;
+
+/// A difference between two versions of a tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffEntry {
+    /// The key only exists in the new tree.
+    Inserted { key: Key, value: Value },
+    /// The key only exists in the old tree.
+    Deleted { key: Key, value: Value },
+    /// The key exists in both trees but with a different value.
+    Modified {
+        key: Key,
+        old_value: Value,
+        new_value: Value,
+    },
+}
+
+impl DiffEntry {
+    /// The key that this entry refers to.
+    pub fn key(&self) -> &Key {
+        match self {
+            DiffEntry::Inserted { key, .. } => key,
+            DiffEntry::Deleted { key, .. } => key,
+            DiffEntry::Modified { key, .. } => key,
+        }
+    }
+}
+
+impl Tree {
+    /// Compute the differences between this (old) tree and the given (new) tree.
+    ///
+    /// Subtrees with the same hash in both trees are skipped without being
+    /// fetched, so the cost is proportional to the size of the changes rather
+    /// than the size of the trees. This also holds when a change shortens or
+    /// extends the label of an internal node, as the longer label is then
+    /// matched bit by bit against the children of the shorter one. Only when
+    /// two subtrees have no keys in common are both collected in full. The
+    /// returned entries are ordered by key.
+    ///
+    /// Both trees must not contain any uncommitted changes.
+    pub fn diff(&self, ctx: Context, other: &Tree) -> Result<Vec<DiffEntry>> {
+        let ctx = ctx.freeze();
+        let old_root = self.cache.borrow().get_pending_root();
+        let new_root = other.cache.borrow().get_pending_root();
+        if !old_root.borrow().clean || !new_root.borrow().clean {
+            return Err(SyncerError::DirtyRoot.into());
+        }
+
+        let mut diff = Vec::new();
+        _diff(
+            &ctx,
+            (self, old_root, 0),
+            (other, new_root, 0),
+            0,
+            Key::new(),
+            &mut diff,
+        )?;
+
+        Ok(diff)
+    }
+
+    fn deref_for_diff(
+        &self,
+        ctx: &Arc<Context>,
+        ptr: NodePtrRef,
+        path: &Key,
+    ) -> Result<Option<NodeRef>> {
+        self.cache.borrow_mut().deref_node_ptr(
+            ctx,
+            ptr,
+            Some(FetcherSyncIterate::new(path, DIFF_PREFETCH)),
+        )
+    }
+}
+
+/// Compare two subtrees covering the same part of the key space.
+///
+/// Each side is given as a tree, a pointer to the subtree root and the number
+/// of bits at the start of the root node's label that have already been matched
+/// (always zero unless the root is an internal node).
+fn _diff(
+    ctx: &Arc<Context>,
+    (old_tree, old_ptr, old_offset): (&Tree, NodePtrRef, Depth),
+    (new_tree, new_ptr, new_offset): (&Tree, NodePtrRef, Depth),
+    bit_depth: Depth,
+    path: Key,
+    diff: &mut Vec<DiffEntry>,
+) -> Result<()> {
+    // Identical subtrees have no differences. Node hashes cover the whole label,
+    // so they can only be compared when no part of it has been matched yet.
+    if old_offset == 0 && new_offset == 0 && old_ptr.borrow().hash == new_ptr.borrow().hash {
+        return Ok(());
+    }
+
+    let old_node = old_tree.deref_for_diff(ctx, old_ptr.clone(), &path)?;
+    let new_node = new_tree.deref_for_diff(ctx, new_ptr.clone(), &path)?;
+
+    if let (Some(old_node), Some(new_node)) = (&old_node, &new_node) {
+        match (&*old_node.borrow(), &*new_node.borrow()) {
+            (NodeBox::Internal(old_n), NodeBox::Internal(new_n)) => {
+                let (old_label, old_label_len) = remaining_label(old_n, old_offset)?;
+                let (new_label, new_label_len) = remaining_label(new_n, new_offset)?;
+                let cp_len = old_label.common_prefix_len(old_label_len, &new_label, new_label_len);
+                let bit_length = bit_depth + cp_len;
+                let new_path = path.merge(
+                    bit_depth,
+                    &old_label.split(cp_len, old_label_len)?.0,
+                    cp_len,
+                )?;
+
+                if cp_len == old_label_len && cp_len == new_label_len {
+                    // Both nodes cover the same set of keys, so compare children pairwise.
+                    _diff(
+                        ctx,
+                        (old_tree, old_n.leaf_node.clone(), 0),
+                        (new_tree, new_n.leaf_node.clone(), 0),
+                        bit_length,
+                        new_path.clone(),
+                        diff,
+                    )?;
+                    _diff(
+                        ctx,
+                        (old_tree, old_n.left.clone(), 0),
+                        (new_tree, new_n.left.clone(), 0),
+                        bit_length,
+                        new_path.append_bit(bit_length, false),
+                        diff,
+                    )?;
+                    _diff(
+                        ctx,
+                        (old_tree, old_n.right.clone(), 0),
+                        (new_tree, new_n.right.clone(), 0),
+                        bit_length,
+                        new_path.append_bit(bit_length, true),
+                        diff,
+                    )?;
+                    return Ok(());
+                }
+
+                if cp_len == new_label_len {
+                    // The old label is longer, so all old keys are under a single child of the
+                    // new node. Everything else in the new node has been inserted.
+                    let bit = old_label.get_bit(cp_len)?;
+                    _diff_prefix(
+                        ctx,
+                        (new_tree, new_n, true),
+                        (old_tree, old_ptr, old_offset + cp_len),
+                        bit,
+                        bit_length,
+                        new_path,
+                        diff,
+                    )?;
+                    return Ok(());
+                }
+
+                if cp_len == old_label_len {
+                    // The new label is longer, so all new keys are under a single child of the
+                    // old node. Everything else in the old node has been deleted.
+                    let bit = new_label.get_bit(cp_len)?;
+                    _diff_prefix(
+                        ctx,
+                        (old_tree, old_n, false),
+                        (new_tree, new_ptr, new_offset + cp_len),
+                        bit,
+                        bit_length,
+                        new_path,
+                        diff,
+                    )?;
+                    return Ok(());
+                }
+            }
+            (NodeBox::Leaf(old_n), NodeBox::Leaf(new_n)) if old_n.key == new_n.key => {
+                // Hashes differ so the values must differ.
+                diff.push(DiffEntry::Modified {
+                    key: old_n.key.clone(),
+                    old_value: old_n.value.clone(),
+                    new_value: new_n.value.clone(),
+                });
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    // The structure of the subtrees differs, compare all entries.
+    let mut old_entries = Vec::new();
+    _collect(
+        ctx,
+        old_tree,
+        old_node,
+        old_offset,
+        bit_depth,
+        path.clone(),
+        &mut old_entries,
+    )?;
+    let mut new_entries = Vec::new();
+    _collect(
+        ctx,
+        new_tree,
+        new_node,
+        new_offset,
+        bit_depth,
+        path,
+        &mut new_entries,
+    )?;
+    let mut old_entries = old_entries.into_iter().peekable();
+    let mut new_entries = new_entries.into_iter().peekable();
+    loop {
+        let ordering = match (old_entries.peek(), new_entries.peek()) {
+            (None, None) => break,
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (Some((old_key, _)), Some((new_key, _))) => old_key.cmp(new_key),
+        };
+
+        match ordering {
+            std::cmp::Ordering::Less => {
+                let (key, value) = old_entries.next().unwrap();
+                diff.push(DiffEntry::Deleted { key, value });
+            }
+            std::cmp::Ordering::Greater => {
+                let (key, value) = new_entries.next().unwrap();
+                diff.push(DiffEntry::Inserted { key, value });
+            }
+            std::cmp::Ordering::Equal => {
+                let (key, old_value) = old_entries.next().unwrap();
+                let (_, new_value) = new_entries.next().unwrap();
+                if old_value != new_value {
+                    diff.push(DiffEntry::Modified {
+                        key,
+                        old_value,
+                        new_value,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare an internal node against a subtree that is entirely covered by one of its children.
+///
+/// The `shorter` node branches at `bit_depth`, while the remaining label of the `longer` subtree
+/// starts with the given `bit`. The child of the shorter node on that side is compared against
+/// the rest of the longer subtree, while its leaf and its other child only exist on the shorter
+/// side. If `shorter_is_new` is set, the shorter node is from the new tree and the entries that
+/// only exist on its side are reported as inserted, otherwise they are reported as deleted.
+fn _diff_prefix(
+    ctx: &Arc<Context>,
+    (shorter_tree, shorter, shorter_is_new): (&Tree, &InternalNode, bool),
+    (longer_tree, longer_ptr, longer_offset): (&Tree, NodePtrRef, Depth),
+    bit: bool,
+    bit_depth: Depth,
+    path: Key,
+    diff: &mut Vec<DiffEntry>,
+) -> Result<()> {
+    let (same_child, other_child) = if bit {
+        (shorter.right.clone(), shorter.left.clone())
+    } else {
+        (shorter.left.clone(), shorter.right.clone())
+    };
+    let same_path = path.append_bit(bit_depth, bit);
+    let other_path = path.append_bit(bit_depth, !bit);
+
+    let push_only = |diff: &mut Vec<DiffEntry>, ptr: NodePtrRef, path: Key| -> Result<()> {
+        let node = shorter_tree.deref_for_diff(ctx, ptr, &path)?;
+        let mut entries = Vec::new();
+        _collect(ctx, shorter_tree, node, 0, bit_depth, path, &mut entries)?;
+        diff.extend(entries.into_iter().map(|(key, value)| {
+            if shorter_is_new {
+                DiffEntry::Inserted { key, value }
+            } else {
+                DiffEntry::Deleted { key, value }
+            }
+        }));
+        Ok(())
+    };
+
+    // The leaf node is a prefix of all other keys in this subtree, so it comes first.
+    push_only(diff, shorter.leaf_node.clone(), path.clone())?;
+    if bit {
+        push_only(diff, other_child, other_path)?;
+    }
+
+    let shorter_side = (shorter_tree, same_child, 0);
+    let longer_side = (longer_tree, longer_ptr, longer_offset);
+    let (old_side, new_side) = if shorter_is_new {
+        (longer_side, shorter_side)
+    } else {
+        (shorter_side, longer_side)
+    };
+    _diff(ctx, old_side, new_side, bit_depth, same_path, diff)?;
+
+    if !bit {
+        push_only(diff, other_child, other_path)?;
+    }
+
+    Ok(())
+}
+
+/// Return the part of an internal node's label that follows the first `offset` bits,
+/// together with its length in bits.
+fn remaining_label(n: &InternalNode, offset: Depth) -> Result<(Key, Depth)> {
+    let (_, label) = n.label.split(offset, n.label_bit_length)?;
+    Ok((label, n.label_bit_length - offset))
+}
+
+/// Collect all entries in the given subtree, ordered by key.
+///
+/// The first `label_offset` bits of the root node's label are assumed to be part of `path`.
+fn _collect(
+    ctx: &Arc<Context>,
+    tree: &Tree,
+    node_ref: Option<NodeRef>,
+    label_offset: Depth,
+    bit_depth: Depth,
+    path: Key,
+    entries: &mut Vec<(Key, Value)>,
+) -> Result<()> {
+    let node_ref = match node_ref {
+        Some(node_ref) => node_ref,
+        None => return Ok(()),
+    };
+
+    match *node_ref.borrow() {
+        NodeBox::Internal(ref n) => {
+            let (label, label_len) = remaining_label(n, label_offset)?;
+            let bit_length = bit_depth + label_len;
+            let new_path = path.merge(bit_depth, &label, label_len)?;
+
+            // The leaf node is a prefix of all other keys in this subtree, so it comes first.
+            for (child, child_path) in vec![
+                (n.leaf_node.clone(), new_path.clone()),
+                (n.left.clone(), new_path.append_bit(bit_length, false)),
+                (n.right.clone(), new_path.append_bit(bit_length, true)),
+            ] {
+                let child_node = tree.deref_for_diff(ctx, child, &child_path)?;
+                _collect(ctx, tree, child_node, 0, bit_length, child_path, entries)?;
+            }
+        }
+        NodeBox::Leaf(ref n) => {
+            entries.push((n.key.clone(), n.value.clone()));
+        }
+    }
+
+    Ok(())
+}
@@ -1,9 +1,25 @@
 use thiserror::Error;
 
+use crate::storage::mkvs::tree::Depth;
+
 #[derive(Error, Debug)]
 pub enum TreeError {
     #[error("mkvs: malformed node")]
     MalformedNode,
     #[error("mkvs: malformed key")]
     MalformedKey,
+    #[error("mkvs: known root mismatch")]
+    KnownRootMismatch,
+    #[error("mkvs: bit {0} out of range for key")]
+    BitOutOfRange(Depth),
+    #[error("mkvs: pointer does not reference a node")]
+    MissingNode,
+    #[error("mkvs: node is dirty")]
+    DirtyNode,
+    #[error("mkvs: unexpected node kind")]
+    UnexpectedNodeKind,
+    #[error("mkvs: key too large ({size} > {max} bytes)")]
+    KeyTooLarge { size: usize, max: usize },
+    #[error("mkvs: value too large ({size} > {max} bytes)")]
+    ValueTooLarge { size: usize, max: usize },
 }
@@ -9,7 +9,12 @@ use super::lookup::FetcherSyncGet;
 
 impl Tree {
     /// Insert a key/value pair into the tree.
+    ///
+    /// Fails with `TreeError::KeyTooLarge` or `TreeError::ValueTooLarge` in case the key or
+    /// the value exceed the limits configured for the tree.
     pub fn insert(&mut self, ctx: Context, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.check_size_limits(key, value)?;
+
         let ctx = ctx.freeze();
         let pending_root = self.cache.borrow().get_pending_root();
         let boxed_key = key.to_vec();
@@ -18,14 +23,32 @@ impl Tree {
         // Remember where the path from root to target node ends (will end).
         self.cache.borrow_mut().mark_position();
 
-        let (new_root, old_val) =
-            self._insert(&ctx, pending_root, 0, &boxed_key, boxed_val.clone(), 0)?;
+        let (new_root, old_val) = self._insert(&ctx, pending_root, 0, &boxed_key, boxed_val, 0)?;
         self.cache.borrow_mut().set_pending_root(new_root.clone());
 
         Ok(old_val)
     }
 
-    fn _insert(
+    pub(super) fn check_size_limits(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let max_key_size = self.key_size_limit();
+        if key.len() > max_key_size {
+            return Err(TreeError::KeyTooLarge {
+                size: key.len(),
+                max: max_key_size,
+            }
+            .into());
+        }
+        if self.max_value_size > 0 && value.len() > self.max_value_size {
+            return Err(TreeError::ValueTooLarge {
+                size: value.len(),
+                max: self.max_value_size,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    pub(super) fn _insert(
         &mut self,
         ctx: &Arc<Context>,
         ptr: NodePtrRef,
@@ -40,7 +63,7 @@ impl Tree {
             Some(FetcherSyncGet::new(key, false)),
         )?;
 
-        let (_, key_remainder) = key.split(bit_depth, key.bit_length());
+        let (_, key_remainder) = key.split(bit_depth, key.bit_length())?;
 
         match classify_noderef!(?node_ref) {
             NodeKind::None => {
@@ -73,7 +96,7 @@ impl Tree {
                                 depth,
                             )?;
                             n.leaf_node = r.0;
-                        } else if key.get_bit(bit_depth + n.label_bit_length) {
+                        } else if key.get_bit(bit_depth + n.label_bit_length)? {
                             // Insert recursively based on the bit value.
                             r = self._insert(
                                 ctx,
@@ -113,7 +136,7 @@ impl Tree {
 
                     // Key mismatches the label at position cp_len. Split the edge and
                     // insert new leaf.
-                    let label_split = n.label.split(cp_len, n.label_bit_length);
+                    let label_split = n.label.split(cp_len, n.label_bit_length)?;
                     label_prefix = label_split.0;
                     n.label = label_split.1;
                     n.label_bit_length = n.label_bit_length - cp_len;
@@ -128,7 +151,7 @@ impl Tree {
                     if key.bit_length() - bit_depth == cp_len {
                         // The key is a prefix of existing path.
                         leaf_node = new_leaf;
-                        if n.label.get_bit(0) {
+                        if n.label.get_bit(0)? {
                             left = NodePointer::null_ptr();
                             right = ptr;
                         } else {
@@ -137,7 +160,7 @@ impl Tree {
                         }
                     } else {
                         leaf_node = NodePointer::null_ptr();
-                        if key_remainder.get_bit(cp_len) {
+                        if key_remainder.get_bit(cp_len)? {
                             left = ptr;
                             right = new_leaf;
                         } else {
@@ -186,7 +209,7 @@ impl Tree {
                         return Ok((ptr.clone(), Some(old_val)));
                     }
 
-                    let (_, leaf_key_remainder) = n.key.split(bit_depth, n.key.bit_length());
+                    let (_, leaf_key_remainder) = n.key.split(bit_depth, n.key.bit_length())?;
                     cp_len = leaf_key_remainder.common_prefix_len(
                         n.key.bit_length() - bit_depth,
                         &key_remainder,
@@ -195,14 +218,14 @@ impl Tree {
 
                     // Key mismatches the label at position cp_len. Split the edge.
                     label_prefix = leaf_key_remainder
-                        .split(cp_len, leaf_key_remainder.bit_length())
+                        .split(cp_len, leaf_key_remainder.bit_length())?
                         .0;
                     let new_leaf = self.cache.borrow_mut().new_leaf_node(key, val);
 
                     if key.bit_length() - bit_depth == cp_len {
                         // Inserted key is a prefix of the label.
                         leaf_node = new_leaf;
-                        if leaf_key_remainder.get_bit(cp_len) {
+                        if leaf_key_remainder.get_bit(cp_len)? {
                             left = NodePointer::null_ptr();
                             right = ptr;
                         } else {
@@ -212,7 +235,7 @@ impl Tree {
                     } else if n.key.bit_length() - bit_depth == cp_len {
                         // Label is a prefix of the inserted key.
                         leaf_node = ptr;
-                        if key_remainder.get_bit(cp_len) {
+                        if key_remainder.get_bit(cp_len)? {
                             left = NodePointer::null_ptr();
                             right = new_leaf;
                         } else {
@@ -221,7 +244,7 @@ impl Tree {
                         }
                     } else {
                         leaf_node = NodePointer::null_ptr();
-                        if key_remainder.get_bit(cp_len) {
+                        if key_remainder.get_bit(cp_len)? {
                             left = ptr;
                             right = new_leaf;
                         } else {
@@ -0,0 +1,145 @@
+//! Tree integrity verification.
+use std::sync::Arc;
+
+use anyhow::Result;
+use io_context::Context;
+
+use crate::{
+    common::crypto::hash::Hash,
+    storage::mkvs::{cache::*, sync::*, tree::*},
+};
+
+use super::iterator::FetcherSyncIterate;
+
+/// Number of nodes to prefetch when a node needs to be fetched during verification.
+const VERIFY_PREFETCH: usize = 100;
+
+/// A node whose contents do not match the hash that it is referenced by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntegrityMismatch {
+    /// Path to the node. For internal nodes this is the common prefix of all
+    /// keys in the subtree, for leaf nodes it is the leaf's key.
+    pub path: Key,
+    /// Length of the path in bits.
+    pub path_bit_length: Depth,
+    /// Hash that the node is referenced by.
+    pub expected: Hash,
+    /// Hash computed from the contents of the node.
+    pub computed: Hash,
+}
+
+impl Tree {
+    /// Walk the whole tree, fetching any nodes that are not available locally,
+    /// and recompute the hash of every node.
+    ///
+    /// Returns all nodes whose contents do not match the hash they are referenced
+    /// by, in key order. An empty result means that the tree is intact.
+    ///
+    /// Only nodes that are already held in memory can be reported as mismatches.
+    /// Nodes fetched from the read syncer are verified against the hashes they are
+    /// referenced by as part of the fetch, so a forged or corrupted remote node
+    /// fails the whole verification with a `VerifierError` instead. The same goes
+    /// for any other failure to fetch a node.
+    ///
+    /// The tree must not contain any uncommitted changes.
+    pub fn verify_integrity(&self, ctx: Context) -> Result<Vec<IntegrityMismatch>> {
+        let ctx = ctx.freeze();
+        let pending_root = self.cache.borrow().get_pending_root();
+        if !pending_root.borrow().clean {
+            return Err(SyncerError::DirtyRoot.into());
+        }
+
+        let mut mismatches = Vec::new();
+        self._verify_integrity(&ctx, pending_root, 0, Key::new(), &mut mismatches)?;
+
+        Ok(mismatches)
+    }
+
+    fn _verify_integrity(
+        &self,
+        ctx: &Arc<Context>,
+        ptr: NodePtrRef,
+        bit_depth: Depth,
+        path: Key,
+        mismatches: &mut Vec<IntegrityMismatch>,
+    ) -> Result<()> {
+        let expected = ptr.borrow().hash;
+        let node_ref = match self.cache.borrow_mut().deref_node_ptr(
+            ctx,
+            ptr,
+            Some(FetcherSyncIterate::new(&path, VERIFY_PREFETCH)),
+        )? {
+            Some(node_ref) => node_ref,
+            None => return Ok(()),
+        };
+
+        let node = node_ref.borrow();
+        match *node {
+            NodeBox::Internal(ref n) => {
+                let bit_length = bit_depth + n.label_bit_length;
+                let new_path = path.merge(bit_depth, &n.label, n.label_bit_length)?;
+
+                // Recompute the hash from the node's contents instead of trusting the
+                // hash cached in the node itself.
+                let mut copy = InternalNode {
+                    label: n.label.clone(),
+                    label_bit_length: n.label_bit_length,
+                    leaf_node: n.leaf_node.clone(),
+                    left: n.left.clone(),
+                    right: n.right.clone(),
+                    ..Default::default()
+                };
+                copy.update_hash();
+                if copy.hash != expected {
+                    mismatches.push(IntegrityMismatch {
+                        path: new_path.clone(),
+                        path_bit_length: bit_length,
+                        expected,
+                        computed: copy.hash,
+                    });
+                }
+
+                // The leaf node is a prefix of all other keys in this subtree, so it comes first.
+                self._verify_integrity(
+                    ctx,
+                    n.leaf_node.clone(),
+                    bit_length,
+                    new_path.clone(),
+                    mismatches,
+                )?;
+                self._verify_integrity(
+                    ctx,
+                    n.left.clone(),
+                    bit_length,
+                    new_path.append_bit(bit_length, false),
+                    mismatches,
+                )?;
+                self._verify_integrity(
+                    ctx,
+                    n.right.clone(),
+                    bit_length,
+                    new_path.append_bit(bit_length, true),
+                    mismatches,
+                )?;
+            }
+            NodeBox::Leaf(ref n) => {
+                let mut copy = LeafNode {
+                    key: n.key.clone(),
+                    value: n.value.clone(),
+                    ..Default::default()
+                };
+                copy.update_hash();
+                if copy.hash != expected {
+                    mismatches.push(IntegrityMismatch {
+                        path: n.key.clone(),
+                        path_bit_length: n.key.bit_length(),
+                        expected,
+                        computed: copy.hash,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
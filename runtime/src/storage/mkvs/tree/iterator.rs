@@ -167,7 +167,7 @@ impl<'tree> TreeIterator<'tree> {
                 if let NodeBox::Internal(ref n) = *node_ref.borrow() {
                     // Internal node.
                     let bit_length = bit_depth + n.label_bit_length;
-                    let new_path = path.merge(bit_depth, &n.label, n.label_bit_length);
+                    let new_path = path.merge(bit_depth, &n.label, n.label_bit_length)?;
 
                     // Check if the key is longer than the current path but lexicographically smaller. In this
                     // case everything in this subtree will be larger so we need to take the first value.
@@ -214,7 +214,7 @@ impl<'tree> TreeIterator<'tree> {
                     }
 
                     // Continue recursively based on a bit value.
-                    if (state == VisitState::At && (!key.get_bit(bit_length) || take_first))
+                    if (state == VisitState::At && (!key.get_bit(bit_length)? || take_first))
                         || state == VisitState::AtLeft
                     {
                         if state == VisitState::At {
@@ -237,7 +237,7 @@ impl<'tree> TreeIterator<'tree> {
                             }
                         }
                         // Key has not been found, continue with search for next key.
-                        key = key.split(bit_length, key.bit_length()).0;
+                        key = key.split(bit_length, key.bit_length())?.0;
                         key = key.append_bit(bit_length, true);
                     }
 
@@ -325,6 +325,10 @@ impl<'tree> mkvs::Iterator for TreeIterator<'tree> {
         }
 
         self.reset();
+        if let Err(error) = check_key_length(key) {
+            self.error = Some(error);
+            return;
+        }
         let pending_root = self.tree.cache.borrow().get_pending_root();
         if let Err(error) = self._next(
             pending_root,
@@ -369,7 +373,7 @@ pub(super) mod test {
     use crate::storage::mkvs::{
         self,
         interop::{Driver, ProtocolServer},
-        Iterator,
+        Iterator, RangeIterator,
     };
 
     #[test]
@@ -615,6 +619,73 @@ pub(super) mod test {
         assert_eq!(2, stats.sync_iterate_count, "sync_iterate_count");
     }
 
+    #[test]
+    fn test_iterator_range() {
+        let mut tree = Tree::make()
+            .with_root_type(RootType::State)
+            .new(Box::new(NoopReadSyncer));
+
+        let items = vec![
+            (b"a".to_vec(), b"0".to_vec()),
+            (b"key".to_vec(), b"1".to_vec()),
+            (b"key 1".to_vec(), b"2".to_vec()),
+            (b"key 2".to_vec(), b"3".to_vec()),
+            (b"key\xff".to_vec(), b"4".to_vec()),
+            (b"kez".to_vec(), b"5".to_vec()),
+            (b"\xff\xff".to_vec(), b"6".to_vec()),
+        ];
+        for (key, value) in items.iter() {
+            tree.insert(Context::background(), key, value).unwrap();
+        }
+
+        // Prefix iteration.
+        let it = RangeIterator::with_prefix(tree.iter(Context::background()), b"key");
+        assert_eq!(it.collect::<Vec<_>>(), items[1..5].to_vec());
+
+        let it = RangeIterator::with_prefix(tree.iter(Context::background()), b"key ");
+        assert_eq!(it.collect::<Vec<_>>(), items[2..4].to_vec());
+
+        let it = RangeIterator::with_prefix(tree.iter(Context::background()), b"\xff");
+        assert_eq!(it.collect::<Vec<_>>(), items[6..].to_vec());
+
+        let it = RangeIterator::with_prefix(tree.iter(Context::background()), b"");
+        assert_eq!(it.collect::<Vec<_>>(), items);
+
+        let it = RangeIterator::with_prefix(tree.iter(Context::background()), b"b");
+        assert_eq!(it.count(), 0);
+
+        // Bounded range iteration.
+        let it = RangeIterator::new(
+            tree.iter(Context::background()),
+            b"key 1",
+            Some(&b"kez"[..]),
+        );
+        assert_eq!(it.collect::<Vec<_>>(), items[2..5].to_vec());
+
+        let it = RangeIterator::new(tree.iter(Context::background()), b"kez", None);
+        assert_eq!(it.collect::<Vec<_>>(), items[5..].to_vec());
+
+        // Paginated iteration through a boxed iterator.
+        let mut start = b"key".to_vec();
+        let mut pages = vec![];
+        loop {
+            let it: Box<dyn mkvs::Iterator> = Box::new(tree.iter(Context::background()));
+            let page: Vec<_> = RangeIterator::new(it, &start, Some(&b"kez"[..]))
+                .take(2)
+                .collect();
+            match page.last() {
+                Some((key, _)) => {
+                    start = key.clone();
+                    start.push(0x00);
+                }
+                None => break,
+            }
+            pages.push(page);
+        }
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages.concat(), items[1..5].to_vec());
+    }
+
     pub(in super::super) fn test_iterator_with<I: mkvs::Iterator>(
         items: &Vec<(Vec<u8>, Vec<u8>)>,
         mut it: I,
@@ -5,6 +5,8 @@ use io_context::Context;
 
 use crate::storage::mkvs::{cache::*, sync::*, tree::*};
 
+use super::tree::check_key_length;
+
 pub(super) struct FetcherSyncGet<'a> {
     key: &'a Key,
     include_siblings: bool,
@@ -57,7 +59,61 @@ impl Tree {
         }
     }
 
+    /// Get an existing key together with a Merkle proof of its inclusion (or absence) under
+    /// the current root.
+    ///
+    /// The tree must not contain any uncommitted changes.
+    pub fn get_with_proof(&self, ctx: Context, key: &[u8]) -> Result<(Option<Vec<u8>>, Proof)> {
+        let (mut values, proof) = self.get_many_with_proof(ctx, &[key])?;
+
+        Ok((values.remove(0), proof))
+    }
+
+    /// Get multiple existing keys together with a single Merkle proof covering all of them.
+    ///
+    /// Nodes shared between the paths to different keys are only included once, so the
+    /// proof is generally much smaller than separate proofs for each key.
+    ///
+    /// The tree must not contain any uncommitted changes.
+    pub fn get_many_with_proof(
+        &self,
+        ctx: Context,
+        keys: &[&[u8]],
+    ) -> Result<(Vec<Option<Vec<u8>>>, Proof)> {
+        let ctx = ctx.freeze();
+        let pending_root = self.cache.borrow().get_pending_root();
+        if !pending_root.borrow().clean {
+            return Err(SyncerError::DirtyRoot.into());
+        }
+        let root_hash = pending_root.borrow().hash;
+        for key in keys {
+            check_key_length(key)?;
+        }
+
+        let mut pb = ProofBuilder::new(root_hash, root_hash);
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            // Remember where the path from root to target node ends (will end).
+            self.cache.borrow_mut().mark_position();
+
+            values.push(self._get(
+                &ctx,
+                pending_root.clone(),
+                0,
+                &key.to_vec(),
+                0,
+                false,
+                Some(&mut pb),
+            )?);
+        }
+        let proof = pb.build(Context::create_child(&ctx))?;
+
+        Ok((values, proof))
+    }
+
     fn _get_top(&self, ctx: Context, key: &[u8], check_only: bool) -> Result<Option<Vec<u8>>> {
+        check_key_length(key)?;
+
         let ctx = ctx.freeze();
         let boxed_key = key.to_vec();
         let pending_root = self.cache.borrow().get_pending_root();
@@ -65,7 +121,7 @@ impl Tree {
         // Remember where the path from root to target node ends (will end).
         self.cache.borrow_mut().mark_position();
 
-        Ok(self._get(&ctx, pending_root, 0, &boxed_key, 0, check_only)?)
+        Ok(self._get(&ctx, pending_root, 0, &boxed_key, 0, check_only, None)?)
     }
 
     fn _get(
@@ -76,6 +132,7 @@ impl Tree {
         key: &Key,
         depth: Depth,
         check_only: bool,
+        mut proof_builder: Option<&mut ProofBuilder>,
     ) -> Result<Option<Value>> {
         let node_ref = self.cache.borrow_mut().deref_node_ptr(
             ctx,
@@ -87,6 +144,11 @@ impl Tree {
             },
         )?;
 
+        // Include nodes in proof if we have a proof builder.
+        if let (Some(pb), Some(node_ref)) = (proof_builder.as_deref_mut(), &node_ref) {
+            pb.include(&node_ref.borrow())?;
+        }
+
         match classify_noderef!(?node_ref) {
             NodeKind::None => {
                 // Reached a nil node, there is nothing here.
@@ -98,6 +160,8 @@ impl Tree {
                     // Internal node.
                     // Does lookup key end here? Look into LeafNode.
                     if key.bit_length() == bit_depth + n.label_bit_length {
+                        // Omit the proof builder as the leaf node is always included with
+                        // the internal node itself.
                         return self._get(
                             ctx,
                             n.leaf_node.clone(),
@@ -105,6 +169,7 @@ impl Tree {
                             key,
                             depth,
                             check_only,
+                            None,
                         );
                     }
 
@@ -114,7 +179,7 @@ impl Tree {
                     }
 
                     // Continue recursively based on a bit value.
-                    if key.get_bit(bit_depth + n.label_bit_length) {
+                    if key.get_bit(bit_depth + n.label_bit_length)? {
                         return self._get(
                             ctx,
                             n.right.clone(),
@@ -122,6 +187,7 @@ impl Tree {
                             key,
                             depth + 1,
                             check_only,
+                            proof_builder,
                         );
                     } else {
                         return self._get(
@@ -131,6 +197,7 @@ impl Tree {
                             key,
                             depth + 1,
                             check_only,
+                            proof_builder,
                         );
                     }
                 }
@@ -70,14 +70,26 @@ impl Marshal for NodeKind {
     }
 }
 
-impl Marshal for InternalNode {
-    fn marshal_binary(&self) -> Result<Vec<u8>> {
+impl NodeBox {
+    /// Encode a node into binary form without any hash pointers (e.g., for proofs).
+    pub fn compact_marshal_binary(&self) -> Result<Vec<u8>> {
+        match self {
+            NodeBox::Internal(ref n) => n.compact_marshal_binary(),
+            NodeBox::Leaf(ref n) => n.marshal_binary(),
+        }
+    }
+}
+
+impl InternalNode {
+    /// Encode an internal node into binary form without any hash pointers (e.g., for proofs).
+    pub fn compact_marshal_binary(&self) -> Result<Vec<u8>> {
+        // Internal node's leaf node is always marshaled along the internal node.
         let leaf_node_binary: Vec<u8>;
         if self.leaf_node.borrow().is_null() {
             leaf_node_binary = vec![NodeKind::None as u8];
         } else {
             leaf_node_binary =
-                noderef_as!(self.leaf_node.borrow().get_node(), Leaf).marshal_binary()?;
+                noderef_as!(self.leaf_node.borrow().get_node()?, Leaf).marshal_binary()?;
         }
 
         let mut result: Vec<u8> = Vec::with_capacity(1 + leaf_node_binary.len() + 2 * Hash::len());
@@ -85,6 +97,14 @@ impl Marshal for InternalNode {
         result.append(&mut self.label_bit_length.marshal_binary()?);
         result.extend_from_slice(&self.label);
         result.extend_from_slice(leaf_node_binary.as_ref());
+
+        Ok(result)
+    }
+}
+
+impl Marshal for InternalNode {
+    fn marshal_binary(&self) -> Result<Vec<u8>> {
+        let mut result = self.compact_marshal_binary()?;
         result.extend_from_slice(self.left.borrow().hash.as_ref());
         result.extend_from_slice(self.right.borrow().hash.as_ref());
 
@@ -1,9 +1,12 @@
 #[macro_use]
 mod macros;
 
+mod apply;
 mod commit;
+mod diff;
 mod errors;
 mod insert;
+mod integrity;
 mod iterator;
 mod lookup;
 mod marshal;
@@ -14,8 +17,10 @@ mod remove;
 mod tree;
 
 pub use commit::*;
+pub use diff::*;
 pub use errors::*;
 pub use insert::*;
+pub use integrity::*;
 pub use iterator::*;
 pub use node::*;
 pub use overlay::*;
@@ -1,8 +1,11 @@
 use std::{cell::RefCell, rc::Rc};
 
+use anyhow::Result;
+use zeroize::Zeroize;
+
 use crate::{
     common::{crypto::hash::Hash, namespace::Namespace},
-    storage::mkvs::{cache::*, marshal::*},
+    storage::mkvs::{cache::*, marshal::*, tree::TreeError},
 };
 
 /// Common interface for node-like objects in the tree.
@@ -14,7 +17,7 @@ pub trait Node {
     /// Recompute the node's hash.
     fn update_hash(&mut self);
     /// Duplicate the node but include only hash references.
-    fn extract(&self) -> NodeRef;
+    fn extract(&self) -> Result<NodeRef>;
 }
 
 /// Storage root type.
@@ -84,7 +87,7 @@ impl Node for NodeBox {
         }
     }
 
-    fn extract(&self) -> NodeRef {
+    fn extract(&self) -> Result<NodeRef> {
         match self {
             NodeBox::Internal(ref n) => n.extract(),
             NodeBox::Leaf(ref n) => n.extract(),
@@ -162,46 +165,45 @@ impl NodePointer {
     }
 
     /// Get a reference to the node the pointer is pointing to.
-    pub fn get_node(&self) -> NodeRef {
-        match &self.node {
-            None => panic!("mkvs: get_node called on pointer without a node"),
-            Some(node) => node.clone(),
-        }
+    pub fn get_node(&self) -> Result<NodeRef> {
+        self.node
+            .clone()
+            .ok_or_else(|| TreeError::MissingNode.into())
     }
 
     /// Return a copy of this pointer containing only hash references.
-    pub fn extract(&self) -> NodePtrRef {
+    pub fn extract(&self) -> Result<NodePtrRef> {
         if !self.clean {
-            panic!("mkvs: extract called on dirty pointer");
+            return Err(TreeError::DirtyNode.into());
         }
-        Rc::new(RefCell::new(NodePointer {
+        Ok(Rc::new(RefCell::new(NodePointer {
             clean: true,
             hash: self.hash,
             ..Default::default()
-        }))
+        })))
     }
 
     // Make deep copy of the Pointer to LeafNode excluding LRU and DBInternal.
     //
-    // Panics, if it's called on non-leaf node pointer.
-    fn copy_leaf_ptr(&self) -> NodePtrRef {
+    // Fails if it's called on a dirty or non-leaf node pointer.
+    fn copy_leaf_ptr(&self) -> Result<NodePtrRef> {
         if !self.has_node() {
-            return NodePointer::null_ptr();
+            return Ok(NodePointer::null_ptr());
         }
 
         if !self.clean {
-            panic!("mkvs: copy_leaf_ptr called on dirty pointer");
+            return Err(TreeError::DirtyNode.into());
         }
-        if let Some(ref some_node) = self.node {
-            let nyoo = noderef_as!(some_node, Leaf).copy();
-            Rc::new(RefCell::new(NodePointer {
+        let node_ref = self.get_node()?;
+        let node = node_ref.borrow();
+        match *node {
+            NodeBox::Leaf(ref n) => Ok(Rc::new(RefCell::new(NodePointer {
                 clean: true,
                 hash: self.hash,
-                node: Some(Rc::new(RefCell::new(NodeBox::Leaf(nyoo)))),
+                node: Some(Rc::new(RefCell::new(NodeBox::Leaf(n.copy())))),
                 ..Default::default()
-            }))
-        } else {
-            panic!("mkvs: copy_leaf_ptr called on a non-leaf pointer");
+            }))),
+            NodeBox::Internal(_) => Err(TreeError::UnexpectedNodeKind.into()),
         }
     }
 }
@@ -216,7 +218,15 @@ impl CacheItem for NodePointer {
     }
 
     fn get_cached_size(&self) -> usize {
-        1
+        // Internal nodes are accounted by count while leaf nodes are accounted
+        // by the size of their key and value.
+        match self.node {
+            Some(ref node) => match *node.borrow() {
+                NodeBox::Internal(_) => 1,
+                NodeBox::Leaf(ref n) => n.key.len() + n.value.len(),
+            },
+            None => 0,
+        }
     }
 }
 
@@ -268,19 +278,19 @@ impl Node for InternalNode {
         ]);
     }
 
-    fn extract(&self) -> NodeRef {
+    fn extract(&self) -> Result<NodeRef> {
         if !self.clean {
-            panic!("mkvs: extract called on dirty node");
+            return Err(TreeError::DirtyNode.into());
         }
-        Rc::new(RefCell::new(NodeBox::Internal(InternalNode {
+        Ok(Rc::new(RefCell::new(NodeBox::Internal(InternalNode {
             clean: true,
             hash: self.hash,
             label: self.label.clone(),
             label_bit_length: self.label_bit_length,
-            leaf_node: self.leaf_node.borrow().copy_leaf_ptr(),
-            left: self.left.borrow().extract(),
-            right: self.right.borrow().extract(),
-        })))
+            leaf_node: self.leaf_node.borrow().copy_leaf_ptr()?,
+            left: self.left.borrow().extract()?,
+            right: self.right.borrow().extract()?,
+        }))))
     }
 }
 
@@ -320,6 +330,14 @@ impl LeafNode {
     }
 }
 
+impl Zeroize for LeafNode {
+    /// Overwrite the key and value with zeros in place.
+    fn zeroize(&mut self) {
+        self.key.as_mut_slice().zeroize();
+        self.value.as_mut_slice().zeroize();
+    }
+}
+
 impl Node for LeafNode {
     fn is_clean(&self) -> bool {
         self.clean
@@ -339,16 +357,16 @@ impl Node for LeafNode {
         ]);
     }
 
-    fn extract(&self) -> NodeRef {
+    fn extract(&self) -> Result<NodeRef> {
         if !self.clean {
-            panic!("mkvs: extract called on dirty node");
+            return Err(TreeError::DirtyNode.into());
         }
-        Rc::new(RefCell::new(NodeBox::Leaf(LeafNode {
+        Ok(Rc::new(RefCell::new(NodeBox::Leaf(LeafNode {
             clean: true,
             hash: self.hash,
             key: self.key.clone(),
             value: self.value.clone(),
-        })))
+        }))))
     }
 }
 
@@ -369,6 +387,11 @@ impl Eq for LeafNode {}
 // max length = 2^size_of(Depth)*8
 pub type Depth = u16;
 
+/// Maximum size of a key in bytes.
+///
+/// Bits of keys are addressed using `Depth`, so the length of a key in bits must fit into it.
+pub const MAX_KEY_SIZE: usize = (Depth::MAX / 8) as usize;
+
 pub trait DepthTrait {
     // Returns the number of bytes needed to fit given bits.
     fn to_bytes(&self) -> usize;
@@ -390,15 +413,22 @@ pub type Key = Vec<u8>;
 
 pub trait KeyTrait {
     /// Get a single bit from the given hash.
-    fn get_bit(&self, bit: Depth) -> bool;
+    ///
+    /// Fails with `TreeError::BitOutOfRange` if the bit is beyond the end of the key.
+    fn get_bit(&self, bit: Depth) -> Result<bool>;
     /// Set a single bit in the given hash and return the result. If bit>self, it resizes new Key.
     fn set_bit(&self, bit: Depth, val: bool) -> Key;
     /// Returns the length of the key in bits.
     fn bit_length(&self) -> Depth;
     /// Bit-wise splits of the key.
-    fn split(&self, split_point: Depth, key_len: Depth) -> (Key, Key);
+    ///
+    /// Fails with `TreeError::BitOutOfRange` if the split point is beyond `key_len` or
+    /// `key_len` is beyond the end of the key.
+    fn split(&self, split_point: Depth, key_len: Depth) -> Result<(Key, Key)>;
     /// Bit-wise merges key of given length with another key of given length.
-    fn merge(&self, key_len: Depth, k2: &Key, k2_len: Depth) -> Key;
+    ///
+    /// Fails with `TreeError::BitOutOfRange` if either length is beyond the end of its key.
+    fn merge(&self, key_len: Depth, k2: &Key, k2_len: Depth) -> Result<Key>;
     /// Appends the given bit to the key.
     fn append_bit(&self, key_len: Depth, bit: bool) -> Key;
     /// Computes length of common prefix of k and k2 with given bit lengths.
@@ -406,8 +436,11 @@ pub trait KeyTrait {
 }
 
 impl KeyTrait for Key {
-    fn get_bit(&self, bit: Depth) -> bool {
-        (self[(bit / 8) as usize] & (1 << (7 - (bit % 8)))) != 0
+    fn get_bit(&self, bit: Depth) -> Result<bool> {
+        let byte = self
+            .get((bit / 8) as usize)
+            .ok_or(TreeError::BitOutOfRange(bit))?;
+        Ok((byte & (1 << (7 - (bit % 8)))) != 0)
     }
 
     fn set_bit(&self, bit: Depth, val: bool) -> Key {
@@ -432,12 +465,12 @@ impl KeyTrait for Key {
         (self.len() * 8) as Depth
     }
 
-    fn split(&self, split_point: Depth, key_len: Depth) -> (Key, Key) {
+    fn split(&self, split_point: Depth, key_len: Depth) -> Result<(Key, Key)> {
         if split_point > key_len {
-            panic!(
-                "mkvs: split_point {} greater than key_len {}",
-                split_point, key_len
-            );
+            return Err(TreeError::BitOutOfRange(split_point).into());
+        }
+        if key_len.to_bytes() > self.len() {
+            return Err(TreeError::BitOutOfRange(key_len).into());
         }
 
         let prefix_len = split_point.to_bytes();
@@ -462,19 +495,23 @@ impl KeyTrait for Key {
             }
         }
 
-        (prefix, suffix)
+        Ok((prefix, suffix))
     }
 
-    fn merge(&self, key_len: Depth, k2: &Key, k2_len: Depth) -> Key {
-        let mut key_len_bytes = (key_len as usize) / 8;
-        if key_len % 8 != 0 {
-            key_len_bytes += 1;
+    fn merge(&self, key_len: Depth, k2: &Key, k2_len: Depth) -> Result<Key> {
+        let key_len_bytes = key_len.to_bytes();
+        if key_len_bytes > self.len() {
+            return Err(TreeError::BitOutOfRange(key_len).into());
+        }
+        let k2_len_bytes = k2_len.to_bytes();
+        if k2_len_bytes > k2.len() {
+            return Err(TreeError::BitOutOfRange(k2_len).into());
         }
 
         let mut new_key: Key = vec![0; (key_len + k2_len).to_bytes()];
         new_key[..key_len_bytes].clone_from_slice(&self[..key_len_bytes]);
 
-        for i in 0..k2.len() as usize {
+        for i in 0..k2_len_bytes {
             // First set the right chunk of the previous byte
             if key_len % 8 != 0 && key_len_bytes > 0 {
                 new_key[key_len_bytes + i - 1] |= k2[i] >> (key_len % 8);
@@ -487,7 +524,7 @@ impl KeyTrait for Key {
             }
         }
 
-        new_key
+        Ok(new_key)
     }
 
     fn append_bit(&self, key_len: Depth, val: bool) -> Key {
@@ -1,5 +1,7 @@
 use std::{cell::RefCell, rc::Rc, str::FromStr};
 
+use zeroize::Zeroize;
+
 use crate::{
     common::crypto::hash::Hash,
     storage::mkvs::{marshal::*, tree::*},
@@ -95,6 +97,19 @@ fn test_serialization_internal() {
     assert_eq!(false, decoded_int_node.right.borrow().node.is_some());
 }
 
+#[test]
+fn test_zeroize_leaf() {
+    let mut leaf_node = LeafNode {
+        key: b"a golden key".to_vec(),
+        value: b"value".to_vec(),
+        ..Default::default()
+    };
+
+    leaf_node.zeroize();
+    assert_eq!(leaf_node.key, vec![0; 12]);
+    assert_eq!(leaf_node.value, vec![0; 5]);
+}
+
 #[test]
 fn test_hash_leaf() {
     let mut leaf_node = LeafNode {
@@ -177,60 +192,60 @@ fn test_key_append_split_merge() {
 
     // byte-aligned split
     let key: Key = vec![0xaa, 0xbb, 0xcc, 0xdd];
-    let (p, s) = key.split(16, 32);
+    let (p, s) = key.split(16, 32).expect("split");
     assert_eq!(vec![0xaa, 0xbb], p);
     assert_eq!(vec![0xcc, 0xdd], s);
 
     // byte-aligned merge
     let key: Key = vec![0xaa, 0xbb];
-    let new_key = key.merge(16, &vec![0xcc, 0xdd], 16);
+    let new_key = key.merge(16, &vec![0xcc, 0xdd], 16).expect("merge");
     assert_eq!(vec![0xaa, 0xbb, 0xcc, 0xdd], new_key);
 
     // empty/full splits
     let key: Key = vec![0xaa, 0xbb, 0xcc, 0xdd];
-    let (p, s) = key.split(0, 32);
+    let (p, s) = key.split(0, 32).expect("split");
     assert_eq!(Key::new(), p);
     assert_eq!(key, s);
-    let (p, s) = key.split(32, 32);
+    let (p, s) = key.split(32, 32).expect("split");
     assert_eq!(key, p);
     assert_eq!(Key::new(), s);
 
     // empty merges
-    let new_key = Key::new().merge(0, &vec![0xaa, 0xbb], 16);
+    let new_key = Key::new().merge(0, &vec![0xaa, 0xbb], 16).expect("merge");
     assert_eq!(vec![0xaa, 0xbb], new_key);
-    let new_key: Key = vec![0xaa, 0xbb].merge(16, &Key::new(), 0);
+    let new_key: Key = vec![0xaa, 0xbb].merge(16, &Key::new(), 0).expect("merge");
     assert_eq!(vec![0xaa, 0xbb], new_key);
 
     // non byte-aligned split
     let key: Key = vec![0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
-    let (p, s) = key.split(17, 64);
+    let (p, s) = key.split(17, 64).expect("split");
     assert_eq!(vec![0x01, 0x23, 0x00], p);
     assert_eq!(vec![0x8a, 0xcf, 0x13, 0x57, 0x9b, 0xde], s);
 
     // ...and merge
-    let new_key = p.merge(17, &s, 64 - 17);
+    let new_key = p.merge(17, &s, 64 - 17).expect("merge");
     assert_eq!(key, new_key);
 
     // non byte-aligned key length split.
     let key: Key = vec![0xff, 0xff, 0xff, 0xff];
-    let (p, s) = key.split(21, 29);
+    let (p, s) = key.split(21, 29).expect("split");
     // Check that split cleans the last 3 unused bits!
     assert_eq!(vec![0xff, 0xff, 0xf8], p);
     assert_eq!(vec![0xff], s);
 
     // ...and merge
-    let new_key = p.merge(21, &s, 8);
+    let new_key = p.merge(21, &s, 8).expect("merge");
     // Merge doesn't obtain original key, because the split cleaned unused bits!
     assert_eq!(vec![0xff, 0xff, 0xff, 0xf8], new_key);
 
     // Special case with zero-length key.
     let key: Key = vec![0x80];
-    let new_key = key.merge(0, &vec![0xf0], 4);
+    let new_key = key.merge(0, &vec![0xf0], 4).expect("merge");
     assert_eq!(vec![0xf0], new_key);
 
     // Special case with extra bytes.
     let key: Key = vec![0x41, 0x6b, 0x00];
-    let new_key = key.merge(16, &vec![0x37], 8);
+    let new_key = key.merge(16, &vec![0x37], 8).expect("merge");
     assert_eq!(vec![0x41, 0x6b, 0x37], new_key);
 }
 
@@ -258,3 +273,64 @@ fn test_key_common_prefix_len() {
     assert_eq!(12, key.common_prefix_len(13, &vec![0xab, 0xcd], 12));
     assert_eq!(12, key.common_prefix_len(12, &vec![0xab, 0xcd], 13));
 }
+
+#[test]
+fn test_key_get_bit() {
+    let key: Key = vec![0x80, 0x01];
+    assert_eq!(true, key.get_bit(0).expect("get_bit"));
+    assert_eq!(false, key.get_bit(1).expect("get_bit"));
+    assert_eq!(true, key.get_bit(15).expect("get_bit"));
+
+    let result = key.get_bit(16);
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::BitOutOfRange(16))
+    ));
+}
+
+#[test]
+fn test_key_split_merge_out_of_range() {
+    let key: Key = vec![0xaa, 0xbb];
+    assert!(matches!(
+        key.split(17, 16).unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::BitOutOfRange(17))
+    ));
+    assert!(matches!(
+        key.split(8, 24).unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::BitOutOfRange(24))
+    ));
+    assert!(matches!(
+        key.merge(24, &vec![0xcc], 8)
+            .unwrap_err()
+            .downcast_ref::<TreeError>(),
+        Some(TreeError::BitOutOfRange(24))
+    ));
+    assert!(matches!(
+        key.merge(16, &vec![0xcc], 16)
+            .unwrap_err()
+            .downcast_ref::<TreeError>(),
+        Some(TreeError::BitOutOfRange(16))
+    ));
+}
+
+#[test]
+fn test_pointer_errors() {
+    let ptr = NodePointer::hash_ptr(Hash::digest_bytes(b"not resolved"));
+    assert!(matches!(
+        ptr.borrow()
+            .get_node()
+            .unwrap_err()
+            .downcast_ref::<TreeError>(),
+        Some(TreeError::MissingNode)
+    ));
+
+    let leaf_node = LeafNode {
+        key: b"a golden key".to_vec(),
+        value: b"value".to_vec(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        leaf_node.extract().unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::DirtyNode)
+    ));
+}
@@ -1,10 +1,12 @@
 use std::{
     collections::{btree_map, BTreeMap, HashSet},
     iter::{Iterator, Peekable},
+    mem,
 };
 
 use anyhow::{Error, Result};
 use io_context::Context;
+use zeroize::Zeroize;
 
 use crate::{
     common::{crypto::hash::Hash, namespace::Namespace},
@@ -16,6 +18,9 @@ use crate::{
 ///
 /// While updates (inserts, removes) are stored in the overlay, reads are not cached in the overlay
 /// as the inner tree has its own cache and double caching makes less sense.
+///
+/// Buffered values are zeroized when they are replaced, committed or discarded together with the
+/// overlay, and so are the previous values from the inner tree that are replaced on commit.
 pub struct OverlayTree<T: mkvs::FallibleMKVS> {
     inner: T,
     overlay: BTreeMap<Vec<u8>, Vec<u8>>,
@@ -44,10 +49,17 @@ impl<T: mkvs::FallibleMKVS> OverlayTree<T> {
     }
 
     /// Insert a key/value pair into the tree.
+    ///
+    /// The pair is checked against the size limits of the inner tree before it is buffered, so
+    /// that a later commit cannot fail half-way through because of it.
     pub fn insert(&mut self, ctx: Context, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inner.check_size_limits(key, value)?;
+
         let previous = self.get(ctx, key)?;
 
-        self.overlay.insert(key.to_owned(), value.to_owned());
+        if let Some(mut buffered) = self.overlay.insert(key.to_owned(), value.to_owned()) {
+            buffered.zeroize();
+        }
         self.dirty.insert(key.to_owned());
 
         Ok(previous)
@@ -75,15 +87,22 @@ impl<T: mkvs::FallibleMKVS> OverlayTree<T> {
         OverlayTreeIterator::new(ctx, self)
     }
 
-    /// Commit any modifications to the underlying tree.
+    /// Commit any modifications to the underlying tree, returning the write log of all
+    /// modifications since the last commit.
+    ///
+    /// The write log is ordered by key.
     pub fn commit(&mut self, ctx: Context) -> Result<mkvs::WriteLog> {
         let ctx = ctx.freeze();
         let mut log: mkvs::WriteLog = Vec::new();
 
         // Insert all items present in the overlay.
         for (key, value) in &self.overlay {
-            self.inner
-                .insert(Context::create_child(&ctx), &key, &value)?;
+            if let Some(mut old_value) =
+                self.inner
+                    .insert(Context::create_child(&ctx), &key, &value)?
+            {
+                old_value.zeroize();
+            }
             self.dirty.remove(key);
 
             log.push(mkvs::LogEntry {
@@ -91,11 +110,13 @@ impl<T: mkvs::FallibleMKVS> OverlayTree<T> {
                 value: Some(value.clone()),
             });
         }
-        self.overlay.clear();
+        self.clear_overlay();
 
         // Any remaining dirty items must have been removed.
         for key in &self.dirty {
-            self.inner.remove(Context::create_child(&ctx), key)?;
+            if let Some(mut old_value) = self.inner.remove(Context::create_child(&ctx), key)? {
+                old_value.zeroize();
+            }
 
             log.push(mkvs::LogEntry {
                 key: key.clone(),
@@ -104,9 +125,19 @@ impl<T: mkvs::FallibleMKVS> OverlayTree<T> {
         }
         self.dirty.clear();
 
+        // Removals are tracked in a hash set, so restore key order.
+        log.sort_by(|a, b| a.key.cmp(&b.key));
+
         Ok(log)
     }
 
+    /// Zeroize and drop all buffered values.
+    fn clear_overlay(&mut self) {
+        for (_, mut value) in mem::take(&mut self.overlay) {
+            value.zeroize();
+        }
+    }
+
     /// Commit any modifications to the underlying tree and then immediately commit the underlying
     /// tree, returning the new root hash.
     pub fn commit_both(
@@ -127,6 +158,13 @@ impl<T: mkvs::FallibleMKVS> OverlayTree<T> {
     }
 }
 
+impl<T: mkvs::FallibleMKVS> Drop for OverlayTree<T> {
+    fn drop(&mut self) {
+        // Updates that were never committed are discarded.
+        self.clear_overlay();
+    }
+}
+
 /// An iterator over the `OverlayTree`.
 pub struct OverlayTreeIterator<'tree, T: mkvs::FallibleMKVS> {
     tree: &'tree OverlayTree<T>,
@@ -263,6 +301,10 @@ impl<'tree, T: mkvs::FallibleMKVS> mkvs::Iterator for OverlayTreeIterator<'tree,
 
 impl<T: mkvs::FallibleMKVS> mkvs::MKVS for OverlayTree<T> {
     fn get(&self, ctx: Context, key: &[u8]) -> Option<Vec<u8>> {
+        // Keys that are too long to be addressed can never be present in the tree.
+        if check_key_length(key).is_err() {
+            return None;
+        }
         self.get(ctx, key).unwrap()
     }
 
@@ -278,7 +320,14 @@ impl<T: mkvs::FallibleMKVS> mkvs::MKVS for OverlayTree<T> {
         self.insert(ctx, key, value).unwrap()
     }
 
+    fn try_insert(&mut self, ctx: Context, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.insert(ctx, key, value)
+    }
+
     fn remove(&mut self, ctx: Context, key: &[u8]) -> Option<Vec<u8>> {
+        if check_key_length(key).is_err() {
+            return None;
+        }
         self.remove(ctx, key).unwrap()
     }
 
@@ -343,6 +392,7 @@ mod test {
         // Test that an overlay-only iterator works correctly.
         let it = overlay.iter(Context::background());
         test_iterator_with(&items, it, &tests);
+        drop(overlay);
 
         // Insert some items into the underlying tree.
         for (key, value) in items.iter() {
@@ -425,7 +475,20 @@ mod test {
         test_iterator_with(&items, it, &tests);
 
         // Commit the overlay.
-        overlay.commit(Context::background()).unwrap();
+        let write_log = overlay.commit(Context::background()).unwrap();
+        assert_eq!(
+            write_log,
+            vec![
+                mkvs::LogEntry {
+                    key: b"key 2".to_vec(),
+                    value: None,
+                },
+                mkvs::LogEntry::new(b"key 5", b"fivey"),
+                mkvs::LogEntry::new(b"key 7", b"seven"),
+            ],
+            "write log should contain all updates ordered by key"
+        );
+        drop(overlay);
 
         // Test that all keys can be fetched from an updated tree.
         for (k, expected_v) in &items {
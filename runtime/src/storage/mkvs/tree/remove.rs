@@ -5,12 +5,14 @@ use io_context::Context;
 
 use crate::storage::mkvs::{cache::*, tree::*};
 
-use super::lookup::FetcherSyncGet;
+use super::{lookup::FetcherSyncGet, tree::check_key_length};
 
 impl Tree {
     /// Remove entry with given key, returning the value at the key if the key was previously
     /// in the database.
     pub fn remove(&mut self, ctx: Context, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        check_key_length(key)?;
+
         let ctx = ctx.freeze();
         let boxed_key = key.to_vec();
         let pending_root = self.cache.borrow().get_pending_root();
@@ -24,7 +26,7 @@ impl Tree {
         Ok(old_val)
     }
 
-    fn _remove(
+    pub(super) fn _remove(
         &mut self,
         ctx: &Arc<Context>,
         ptr: NodePtrRef,
@@ -47,11 +49,6 @@ impl Tree {
                 // Remove from internal node and recursively collapse the path, if needed.
                 let node_ref = node_ref.unwrap();
                 let (changed, old_val): (bool, Option<Value>);
-                let (remaining_leaf, remaining_left, remaining_right): (
-                    Option<NodeRef>,
-                    Option<NodeRef>,
-                    Option<NodeRef>,
-                );
                 if let NodeBox::Internal(ref mut n) = *node_ref.borrow_mut() {
                     // Remove from internal node and recursively collapse the branch, if
                     // needed.
@@ -64,7 +61,7 @@ impl Tree {
 
                     let (new_child, c, o) = if key.bit_length() == bit_length {
                         self._remove(ctx, n.leaf_node.clone(), bit_depth, key, depth)?
-                    } else if key.get_bit(bit_length) {
+                    } else if key.get_bit(bit_length)? {
                         self._remove(ctx, n.right.clone(), bit_length, key, depth + 1)?
                     } else {
                         self._remove(ctx, n.left.clone(), bit_length, key, depth + 1)?
@@ -75,120 +72,26 @@ impl Tree {
 
                     if key.bit_length() == bit_length {
                         n.leaf_node = new_child;
-                    } else if key.get_bit(bit_length) {
+                    } else if key.get_bit(bit_length)? {
                         n.right = new_child;
                     } else {
                         n.left = new_child;
                     }
-
-                    // Fetch and check the remaining children.
-                    // NOTE: The leaf node is always included with the internal node.
-                    remaining_leaf = n.leaf_node.borrow().node.clone();
-                    remaining_left = self.cache.borrow_mut().deref_node_ptr(
-                        ctx,
-                        n.left.clone(),
-                        Some(FetcherSyncGet::new(key, true)),
-                    )?;
-                    remaining_right = self.cache.borrow_mut().deref_node_ptr(
-                        ctx,
-                        n.right.clone(),
-                        Some(FetcherSyncGet::new(key, true)),
-                    )?;
                 } else {
                     unreachable!("node kind is Internal");
                 }
 
-                // If exactly one child including LeafNode remains, collapse it.
-                match remaining_leaf {
-                    Some(_) => match remaining_left {
-                        Some(_) => (),
-                        None => match remaining_right {
-                            None => {
-                                let nd_leaf = noderef_as!(node_ref, Internal).leaf_node.clone();
-                                noderef_as_mut!(node_ref, Internal).leaf_node =
-                                    NodePointer::null_ptr();
-                                self.cache.borrow_mut().remove_node(ptr.clone());
-                                return Ok((nd_leaf, true, old_val));
-                            }
-                            Some(_) => (),
-                        },
-                    },
-                    None => {
-                        let mut nd_child: Option<NodeRef> = None;
-                        let mut node_ptr: NodePtrRef = NodePointer::null_ptr();
-                        let mut both_children = true;
-                        match remaining_left {
-                            Some(_) => match remaining_right {
-                                None => {
-                                    node_ptr = noderef_as!(node_ref, Internal).left.clone();
-                                    noderef_as_mut!(node_ref, Internal).left =
-                                        NodePointer::null_ptr();
-                                    nd_child = remaining_left;
-                                    both_children = false;
-                                }
-                                Some(_) => (),
-                            },
-                            None => match remaining_right {
-                                None => (),
-                                Some(_) => {
-                                    node_ptr = noderef_as!(node_ref, Internal).right.clone();
-                                    noderef_as_mut!(node_ref, Internal).right =
-                                        NodePointer::null_ptr();
-                                    nd_child = remaining_right;
-                                    both_children = false;
-                                }
-                            },
-                        }
-
-                        if !both_children {
-                            // If child is an internal node, also fix the label.
-                            match nd_child {
-                                Some(_) => match classify_noderef!(?nd_child) {
-                                    NodeKind::Internal => {
-                                        if let NodeBox::Internal(ref mut inode) =
-                                            *nd_child.unwrap().borrow_mut()
-                                        {
-                                            inode.label =
-                                                noderef_as!(node_ref, Internal).label.merge(
-                                                    noderef_as!(node_ref, Internal)
-                                                        .label_bit_length,
-                                                    &inode.label,
-                                                    inode.label_bit_length,
-                                                );
-                                            inode.label_bit_length +=
-                                                noderef_as!(node_ref, Internal).label_bit_length;
-                                            inode.clean = false;
-                                            node_ptr.borrow_mut().clean = false;
-                                        }
-                                    }
-                                    _ => (),
-                                },
-                                _ => (),
-                            }
-
-                            self.cache.borrow_mut().remove_node(ptr.clone());
-                            return Ok((node_ptr, true, old_val));
-                        }
-                    }
-                };
-
-                // Two or more children including leaf_node remain, just mark dirty bit.
-                if changed {
-                    noderef_as_mut!(node_ref, Internal).clean = false;
-                    ptr.borrow_mut().clean = false;
-                    // No longer eligible for eviction as it is dirty.
-                    self.cache
-                        .borrow_mut()
-                        .rollback_node(ptr.clone(), NodeKind::Internal);
-                }
-
-                return Ok((ptr.clone(), changed, old_val));
+                let (new_ptr, collapsed) =
+                    self.collapse_internal(ctx, ptr, node_ref, key, changed)?;
+                return Ok((new_ptr, changed || collapsed, old_val));
             }
             NodeKind::Leaf => {
                 // Remove from leaf node.
                 let node_ref = node_ref.unwrap();
                 if noderef_as!(node_ref, Leaf).key == *key {
                     let old_val = noderef_as!(node_ref, Leaf).value.clone();
+                    // Release our reference so the removed node can be zeroized if enabled.
+                    drop(node_ref);
                     self.cache.borrow_mut().remove_node(ptr.clone());
                     return Ok((NodePointer::null_ptr(), true, Some(old_val)));
                 }
@@ -197,4 +100,123 @@ impl Tree {
             }
         };
     }
+
+    /// Finish updating an internal node after some of its children have been removed.
+    ///
+    /// If at most one child (including the leaf node) remains, the node is removed and
+    /// the remaining child takes its place. Otherwise the node is marked dirty in case it
+    /// has `changed`. Returns the pointer that should replace the node's pointer and
+    /// whether the node was collapsed.
+    pub(super) fn collapse_internal(
+        &mut self,
+        ctx: &Arc<Context>,
+        ptr: NodePtrRef,
+        node_ref: NodeRef,
+        key: &Key,
+        changed: bool,
+    ) -> Result<(NodePtrRef, bool)> {
+        // Fetch and check the remaining children.
+        // NOTE: The leaf node is always included with the internal node.
+        let remaining_leaf = noderef_as!(node_ref, Internal)
+            .leaf_node
+            .borrow()
+            .node
+            .clone();
+        let left = noderef_as!(node_ref, Internal).left.clone();
+        let right = noderef_as!(node_ref, Internal).right.clone();
+        let remaining_left = self.cache.borrow_mut().deref_node_ptr(
+            ctx,
+            left,
+            Some(FetcherSyncGet::new(key, true)),
+        )?;
+        let remaining_right = self.cache.borrow_mut().deref_node_ptr(
+            ctx,
+            right,
+            Some(FetcherSyncGet::new(key, true)),
+        )?;
+
+        // If exactly one child including LeafNode remains, collapse it.
+        match remaining_leaf {
+            Some(_) => match remaining_left {
+                Some(_) => (),
+                None => match remaining_right {
+                    None => {
+                        let nd_leaf = noderef_as!(node_ref, Internal).leaf_node.clone();
+                        noderef_as_mut!(node_ref, Internal).leaf_node = NodePointer::null_ptr();
+                        self.cache.borrow_mut().remove_node(ptr.clone());
+                        return Ok((nd_leaf, true));
+                    }
+                    Some(_) => (),
+                },
+            },
+            None => {
+                let mut nd_child: Option<NodeRef> = None;
+                let mut node_ptr: NodePtrRef = NodePointer::null_ptr();
+                let mut both_children = true;
+                match remaining_left {
+                    Some(_) => match remaining_right {
+                        None => {
+                            node_ptr = noderef_as!(node_ref, Internal).left.clone();
+                            noderef_as_mut!(node_ref, Internal).left = NodePointer::null_ptr();
+                            nd_child = remaining_left;
+                            both_children = false;
+                        }
+                        Some(_) => (),
+                    },
+                    None => match remaining_right {
+                        None => {
+                            // No children remain (possible when removing a batch of keys).
+                            both_children = false;
+                        }
+                        Some(_) => {
+                            node_ptr = noderef_as!(node_ref, Internal).right.clone();
+                            noderef_as_mut!(node_ref, Internal).right = NodePointer::null_ptr();
+                            nd_child = remaining_right;
+                            both_children = false;
+                        }
+                    },
+                }
+
+                if !both_children {
+                    // If child is an internal node, also fix the label.
+                    match nd_child {
+                        Some(_) => match classify_noderef!(?nd_child) {
+                            NodeKind::Internal => {
+                                if let NodeBox::Internal(ref mut inode) =
+                                    *nd_child.unwrap().borrow_mut()
+                                {
+                                    inode.label = noderef_as!(node_ref, Internal).label.merge(
+                                        noderef_as!(node_ref, Internal).label_bit_length,
+                                        &inode.label,
+                                        inode.label_bit_length,
+                                    )?;
+                                    inode.label_bit_length +=
+                                        noderef_as!(node_ref, Internal).label_bit_length;
+                                    inode.clean = false;
+                                    node_ptr.borrow_mut().clean = false;
+                                }
+                            }
+                            _ => (),
+                        },
+                        _ => (),
+                    }
+
+                    self.cache.borrow_mut().remove_node(ptr.clone());
+                    return Ok((node_ptr, true));
+                }
+            }
+        };
+
+        // Two or more children including leaf_node remain, just mark dirty bit.
+        if changed {
+            noderef_as_mut!(node_ref, Internal).clean = false;
+            ptr.borrow_mut().clean = false;
+            // No longer eligible for eviction as it is dirty.
+            self.cache
+                .borrow_mut()
+                .rollback_node(ptr.clone(), NodeKind::Internal);
+        }
+
+        Ok((ptr, false))
+    }
 }
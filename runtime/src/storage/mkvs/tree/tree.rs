@@ -12,6 +12,9 @@ use crate::{
 pub struct Options {
     node_capacity: usize,
     value_capacity: usize,
+    max_key_size: usize,
+    max_value_size: usize,
+    zeroize_values: bool,
     root: Option<Root>,
     root_type: Option<RootType>,
 }
@@ -19,10 +22,10 @@ pub struct Options {
 impl Options {
     /// Set the capacity of the underlying in-memory cache.
     ///
-    /// * `node_capacity` is the maximum number of nodes held by the
-    ///   cache before eviction.
-    /// * `value_capacity` is the total size, in bytes, of values held
-    ///   by the cache before eviction.
+    /// * `node_capacity` is the maximum number of internal nodes held by
+    ///   the cache before eviction.
+    /// * `value_capacity` is the total size, in bytes, of leaf keys and
+    ///   values held by the cache before eviction.
     ///
     /// If set to 0, the relevant cache will have an unlimited capacity. If left
     /// unspecified, the cache will default to 50_000 for nodes and 16MB for values.
@@ -32,6 +35,35 @@ impl Options {
         self
     }
 
+    /// Set the maximum size, in bytes, of keys that can be inserted into the tree.
+    ///
+    /// Inserting a larger key fails with `TreeError::KeyTooLarge`. If set to 0 or
+    /// left unspecified, keys are only limited to `MAX_KEY_SIZE`, which is also the
+    /// upper bound for this setting.
+    pub fn with_max_key_size(mut self, max_key_size: usize) -> Self {
+        self.max_key_size = max_key_size;
+        self
+    }
+
+    /// Set the maximum size, in bytes, of values that can be inserted into the tree.
+    ///
+    /// Inserting a larger value fails with `TreeError::ValueTooLarge`. If set to 0 or
+    /// left unspecified, value size is not limited.
+    pub fn with_max_value_size(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = max_value_size;
+        self
+    }
+
+    /// Set whether leaf keys and values are zeroized when they are dropped from the cache.
+    ///
+    /// This covers nodes evicted from the cache, nodes removed from the tree and the
+    /// contents of the cache when the tree itself is dropped. Values that have been
+    /// returned to the caller are not affected. Disabled by default.
+    pub fn with_zeroize_values(mut self, zeroize_values: bool) -> Self {
+        self.zeroize_values = zeroize_values;
+        self
+    }
+
     /// Set an existing root as the root for the new tree.
     ///
     /// Either this or a root type must be specified to construct a new
@@ -72,6 +104,9 @@ impl Options {
 pub struct Tree {
     pub(crate) cache: RefCell<Box<LRUCache>>,
     pub(crate) root_type: RootType,
+    pub(crate) max_key_size: usize,
+    pub(crate) max_value_size: usize,
+    pub(crate) zeroize_values: bool,
 }
 
 // Tree is Send as long as ownership of internal Rcs cannot leak out via any of its methods.
@@ -91,8 +126,12 @@ impl Tree {
                 opts.value_capacity,
                 read_syncer,
                 root_type,
+                opts.zeroize_values,
             )),
             root_type: root_type,
+            max_key_size: opts.max_key_size,
+            max_value_size: opts.max_value_size,
+            zeroize_values: opts.zeroize_values,
         };
 
         if let Some(root) = opts.root {
@@ -109,15 +148,43 @@ impl Tree {
         tree
     }
 
+    /// Return the maximum size, in bytes, of keys that can be inserted into the tree.
+    pub fn key_size_limit(&self) -> usize {
+        match self.max_key_size {
+            0 => MAX_KEY_SIZE,
+            max => max.min(MAX_KEY_SIZE),
+        }
+    }
+
     /// Return an options struct to chain configuration calls on.
     pub fn make() -> Options {
         Options {
             node_capacity: 50_000,
             value_capacity: 16 * 1024 * 1024,
+            max_key_size: 0,
+            max_value_size: 0,
+            zeroize_values: false,
             root: None,
             root_type: None,
         }
     }
+
+    /// Return statistics about the contents and usage of the tree's in-memory cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.borrow().stats()
+    }
+}
+
+/// Make sure that the key is short enough for its bits to be addressable.
+pub(crate) fn check_key_length(key: &[u8]) -> Result<()> {
+    if key.len() > MAX_KEY_SIZE {
+        return Err(TreeError::KeyTooLarge {
+            size: key.len(),
+            max: MAX_KEY_SIZE,
+        }
+        .into());
+    }
+    Ok(())
 }
 
 impl fmt::Debug for Tree {
@@ -143,6 +210,10 @@ impl mkvs::FallibleMKVS for Tree {
         Tree::remove(self, ctx, key)
     }
 
+    fn check_size_limits(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        Tree::check_size_limits(self, key, value)
+    }
+
     fn prefetch_prefixes(
         &self,
         ctx: Context,
@@ -1,6 +1,9 @@
+use anyhow::Result;
 use io_context::Context;
 use serde_json;
-use std::{collections::HashSet, fs::File, io::BufReader, iter, iter::FromIterator, path::Path};
+use std::{
+    any::Any, collections::HashSet, fs::File, io::BufReader, iter, iter::FromIterator, path::Path,
+};
 
 use crate::{
     common::crypto::hash::Hash,
@@ -853,6 +856,76 @@ fn test_syncer_insert() {
     assert_eq!(0, stats.sync_iterate_count, "sync_iterate count");
 }
 
+#[test]
+fn test_syncer_apply_insert() {
+    let server = ProtocolServer::new(None);
+
+    let mut tree = OverlayTree::new(
+        Tree::make()
+            .with_capacity(0, 0)
+            .with_root_type(RootType::State)
+            .new(Box::new(NoopReadSyncer)),
+    );
+
+    let (keys, values) = generate_key_value_pairs();
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+    }
+
+    let (write_log, hash) = tree
+        .commit_both(Context::background(), Default::default(), 0)
+        .expect("commit");
+    server.apply(&write_log, hash, Default::default(), 0);
+
+    let stats = StatsCollector::new(server.read_sync());
+    let mut remote_tree = Tree::make()
+        .with_capacity(0, 0)
+        .with_root(Root {
+            root_type: RootType::State,
+            hash,
+            ..Default::default()
+        })
+        .new(Box::new(stats));
+
+    // Resolve the paths to all updated keys, but not their siblings.
+    let mut ops = Vec::new();
+    for i in (0..keys.len()).step_by(10) {
+        remote_tree
+            .get(Context::background(), keys[i].as_slice())
+            .expect("get");
+        ops.push(LogEntry::new(keys[i].as_slice(), b"updated"));
+    }
+    let sync_get_count = {
+        let cache = remote_tree.cache.borrow();
+        let stats = cache
+            .get_read_syncer()
+            .as_any()
+            .downcast_ref::<StatsCollector>()
+            .expect("stats");
+        stats.sync_get_count
+    };
+
+    // An insert-only batch cannot collapse any nodes, so it must not fetch anything else.
+    remote_tree
+        .apply(Context::background(), &ops)
+        .expect("apply");
+
+    let cache = remote_tree.cache.borrow();
+    let stats = cache
+        .get_read_syncer()
+        .as_any()
+        .downcast_ref::<StatsCollector>()
+        .expect("stats");
+    assert_eq!(sync_get_count, stats.sync_get_count, "sync_get count");
+    assert_eq!(0, stats.sync_get_prefixes_count, "sync_get_prefixes count");
+    assert_eq!(0, stats.sync_iterate_count, "sync_iterate count");
+}
+
 #[test]
 fn test_syncer_writelog_remove() {
     let server = ProtocolServer::new(None);
@@ -951,10 +1024,42 @@ fn test_syncer_prefetch_prefixes() {
     assert_eq!(0, stats.sync_iterate_count, "sync_iterate count");
 }
 
+/// A read syncer that answers every request with the same, possibly invalid, proof.
+struct StaticReadSyncer {
+    proof: Proof,
+}
+
+impl ReadSync for StaticReadSyncer {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn sync_get(&mut self, _ctx: Context, _request: GetRequest) -> Result<ProofResponse> {
+        Ok(ProofResponse {
+            proof: self.proof.clone(),
+        })
+    }
+
+    fn sync_get_prefixes(
+        &mut self,
+        _ctx: Context,
+        _request: GetPrefixesRequest,
+    ) -> Result<ProofResponse> {
+        Ok(ProofResponse {
+            proof: self.proof.clone(),
+        })
+    }
+
+    fn sync_iterate(&mut self, _ctx: Context, _request: IterateRequest) -> Result<ProofResponse> {
+        Ok(ProofResponse {
+            proof: self.proof.clone(),
+        })
+    }
+}
+
 #[test]
-fn test_value_eviction() {
+fn test_syncer_invalid_proof() {
     let mut tree = Tree::make()
-        .with_capacity(0, 512)
         .with_root_type(RootType::State)
         .new(Box::new(NoopReadSyncer));
 
@@ -967,29 +1072,145 @@ fn test_value_eviction() {
         )
         .expect("insert");
     }
-    Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    let (_, proof) = tree
+        .get_with_proof(Context::background(), keys[0].as_slice())
+        .expect("get_with_proof");
+
+    let remote_get = |proof: Proof, key: &[u8]| {
+        let remote_tree = Tree::make()
+            .with_root(Root {
+                root_type: RootType::State,
+                hash,
+                ..Default::default()
+            })
+            .new(Box::new(StaticReadSyncer { proof }));
+        remote_tree.get(Context::background(), key)
+    };
 
+    // A valid proof is accepted.
     assert_eq!(
-        999,
-        tree.cache.borrow().stats().internal_node_count,
-        "cache.internal_node_count"
+        Some(values[0].clone()),
+        remote_get(proof.clone(), keys[0].as_slice()).expect("get")
     );
-    // Only a subset of the leaf values should remain in cache.
-    assert_eq!(
-        512,
-        tree.cache.borrow().stats().leaf_value_size,
-        "cache.leaf_value_size"
+
+    // A proof for some other root is rejected.
+    let mut forged = proof.clone();
+    forged.untrusted_root = Hash::digest_bytes(b"forged root");
+    let err = remote_get(forged, keys[0].as_slice()).expect_err("get should fail");
+    assert!(matches!(
+        err.downcast_ref::<VerifierError>(),
+        Some(VerifierError::UnexpectedRoot { .. })
+    ));
+
+    // A proof with tampered contents does not match the root.
+    let mut tampered = proof.clone();
+    let entry = tampered
+        .entries
+        .iter_mut()
+        .rev()
+        .find_map(|entry| entry.as_mut())
+        .expect("proof should have entries");
+    let last = entry.len() - 1;
+    entry[last] ^= 0xff;
+    let err = remote_get(tampered, keys[0].as_slice()).expect_err("get should fail");
+    assert!(matches!(
+        err.downcast_ref::<VerifierError>(),
+        Some(VerifierError::BadRoot { .. })
+    ));
+
+    // A valid proof which does not cover the requested key is incomplete.
+    let err = remote_get(proof, keys[1].as_slice()).expect_err("get should fail");
+    let err = err
+        .downcast_ref::<VerifierError>()
+        .expect("error should be a verifier error");
+    assert!(matches!(err, VerifierError::MissingNode));
+    assert!(err.is_missing());
+}
+
+#[test]
+fn test_get_with_proof() {
+    let server = ProtocolServer::new(None);
+
+    let mut tree = Tree::make()
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+
+    let (keys, values) = generate_key_value_pairs_ex("".to_string(), 100);
+    let mut write_log = WriteLog::new();
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+        write_log.push(LogEntry::new(keys[i].as_slice(), values[i].as_slice()));
+    }
+
+    // Proofs can only be generated for committed roots.
+    let result = tree.get_with_proof(Context::background(), keys[0].as_slice());
+    assert!(
+        result.is_err(),
+        "get_with_proof should fail on a dirty root"
     );
+
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    server.apply(&write_log, hash, Default::default(), 0);
+
+    let root = Root {
+        root_type: RootType::State,
+        hash,
+        ..Default::default()
+    };
+    let mut remote = server.read_sync();
+    let pv = ProofVerifier;
+
+    let mut lookup_keys = keys.clone();
+    lookup_keys.push(b"missing key".to_vec());
+    lookup_keys.push(b"key 1 missing".to_vec());
+    for key in lookup_keys {
+        let (value, proof) = tree
+            .get_with_proof(Context::background(), &key)
+            .expect("get_with_proof");
+        assert_eq!(
+            value,
+            tree.get(Context::background(), &key).expect("get"),
+            "get_with_proof should return the same value as get"
+        );
+
+        // Proof should verify against the committed root.
+        pv.verify_proof(Context::background(), hash, &proof)
+            .expect("verify proof should not fail with a valid proof");
+
+        // Proof should match the one generated by Go.
+        let go_proof = remote
+            .sync_get(
+                Context::background(),
+                GetRequest {
+                    tree: TreeID {
+                        root,
+                        position: hash,
+                    },
+                    key: key.clone(),
+                    include_siblings: false,
+                },
+            )
+            .expect("sync_get")
+            .proof;
+        assert_eq!(proof, go_proof, "proof should match the Go implementation");
+    }
 }
 
 #[test]
-fn test_node_eviction() {
+fn test_proof_for_key() {
     let mut tree = Tree::make()
-        .with_capacity(128, 0)
         .with_root_type(RootType::State)
         .new(Box::new(NoopReadSyncer));
 
-    let (keys, values) = generate_key_value_pairs_ex("foo".to_string(), 150);
+    let (keys, values) = generate_key_value_pairs_ex("".to_string(), 100);
     for i in 0..keys.len() {
         tree.insert(
             Context::background(),
@@ -998,9 +1219,68 @@ fn test_node_eviction() {
         )
         .expect("insert");
     }
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    let pv = ProofVerifier;
+
+    // Inclusion proofs.
+    for i in 0..keys.len() {
+        let (_, proof) = tree
+            .get_with_proof(Context::background(), keys[i].as_slice())
+            .expect("get_with_proof");
+        let value = pv
+            .verify_proof_for_key(Context::background(), hash, &proof, keys[i].as_slice())
+            .expect("verify proof should not fail with a valid proof");
+        assert_eq!(Some(values[i].clone()), value, "proof should include value");
+    }
+
+    // Absence proofs.
+    let missing_keys: &[&[u8]] = &[b"", b"k", b"key", b"key 1 missing", b"key 100", b"zzz"];
+    for &key in missing_keys {
+        let (value, proof) = tree
+            .get_with_proof(Context::background(), key)
+            .expect("get_with_proof");
+        assert_eq!(None, value);
+        let value = pv
+            .verify_proof_for_key(Context::background(), hash, &proof, key)
+            .expect("verify proof should not fail with a valid proof");
+        assert_eq!(None, value, "proof should show absence of key");
+    }
+
+    // Proof for a different key should not be accepted as a proof of absence.
+    let (_, proof) = tree
+        .get_with_proof(Context::background(), keys[1].as_slice())
+        .expect("get_with_proof");
+    let result = pv.verify_proof_for_key(Context::background(), hash, &proof, keys[99].as_slice());
+    let err = result.expect_err("verify proof should fail for a key not covered by the proof");
+    assert!(
+        err.downcast_ref::<VerifierError>()
+            .map(VerifierError::is_missing)
+            .unwrap_or(false),
+        "verify proof should report missing data for a key not covered by the proof"
+    );
+
+    // Empty tree.
+    let mut tree = Tree::make()
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
     Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    let (_, proof) = tree
+        .get_with_proof(Context::background(), b"foo")
+        .expect("get_with_proof");
+    let value = pv
+        .verify_proof_for_key(Context::background(), Hash::empty_hash(), &proof, b"foo")
+        .expect("verify proof should not fail with a valid proof");
+    assert_eq!(None, value, "proof should show absence of key");
+}
 
-    let (keys, values) = generate_key_value_pairs_ex("foo key 1".to_string(), 150);
+#[test]
+fn test_multi_key_proof() {
+    let mut tree = Tree::make()
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+
+    let (keys, values) = generate_key_value_pairs();
     for i in 0..keys.len() {
         tree.insert(
             Context::background(),
@@ -1009,19 +1289,966 @@ fn test_node_eviction() {
         )
         .expect("insert");
     }
-    Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    let pv = ProofVerifier;
+
+    let mut lookup_keys: Vec<&[u8]> = keys[..50].iter().map(|k| k.as_slice()).collect();
+    lookup_keys.push(b"missing key");
+    let (got_values, proof) = tree
+        .get_many_with_proof(Context::background(), &lookup_keys)
+        .expect("get_many_with_proof");
+    assert_eq!(got_values.len(), lookup_keys.len());
+    for i in 0..50 {
+        assert_eq!(Some(values[i].clone()), got_values[i]);
+    }
+    assert_eq!(None, got_values[50]);
+
+    // Proof should cover all keys.
+    let verified_values = pv
+        .verify_proof_for_keys(Context::background(), hash, &proof, &lookup_keys)
+        .expect("verify proof should not fail with a valid proof");
+    assert_eq!(got_values, verified_values);
+
+    // Shared nodes should only be included once.
+    let proof_size = |proof: &Proof| -> usize {
+        proof
+            .entries
+            .iter()
+            .map(|e| e.as_ref().map(|e| e.len()).unwrap_or(0))
+            .sum()
+    };
+    let mut separate_size = 0;
+    for key in &lookup_keys {
+        let (_, proof) = tree
+            .get_with_proof(Context::background(), key)
+            .expect("get_with_proof");
+        separate_size += proof_size(&proof);
+    }
+    assert!(
+        proof_size(&proof) < separate_size,
+        "multi-key proof should be smaller than separate proofs"
+    );
 
-    // Only a subset of nodes should remain in cache.
+    // Proof should not cover keys that were not requested.
+    let result =
+        pv.verify_proof_for_keys(Context::background(), hash, &proof, &[keys[999].as_slice()]);
+    assert!(
+        result.is_err(),
+        "verify proof should fail for a key not covered by the proof"
+    );
+}
+
+#[test]
+fn test_apply_write_log() {
+    let mut tree = OverlayTree::new(
+        Tree::make()
+            .with_root_type(RootType::State)
+            .new(Box::new(NoopReadSyncer)),
+    );
+
+    let (keys, values) = generate_key_value_pairs();
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+    }
+    let (write_log_1, hash_1) = tree
+        .commit_both(Context::background(), Default::default(), 1)
+        .expect("commit");
+    assert_eq!(format!("{:?}", hash_1), ALL_ITEMS_ROOT);
+
+    for i in (0..keys.len()).step_by(2) {
+        tree.remove(Context::background(), keys[i].as_slice())
+            .expect("remove");
+    }
+    tree.insert(Context::background(), b"foo", b"bar")
+        .expect("insert");
+    let (write_log_2, hash_2) = tree
+        .commit_both(Context::background(), Default::default(), 2)
+        .expect("commit");
+
+    // Replay write logs on a fresh tree.
+    let mut replica = Tree::make()
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+
+    replica
+        .apply_write_log(Context::background(), &write_log_1)
+        .expect("apply_write_log");
+    replica
+        .commit_known(
+            Context::background(),
+            Root {
+                version: 1,
+                root_type: RootType::State,
+                hash: hash_1,
+                ..Default::default()
+            },
+        )
+        .expect("commit_known");
+
+    // Applying a write log and committing to a wrong root should fail.
+    replica
+        .apply_write_log(Context::background(), &write_log_2)
+        .expect("apply_write_log");
+    let result = replica.commit_known(
+        Context::background(),
+        Root {
+            version: 2,
+            root_type: RootType::State,
+            hash: hash_1,
+            ..Default::default()
+        },
+    );
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::KnownRootMismatch)
+    ));
+
+    // Updates should remain uncommitted so committing to the right root should succeed.
+    replica
+        .commit_known(
+            Context::background(),
+            Root {
+                version: 2,
+                root_type: RootType::State,
+                hash: hash_2,
+                ..Default::default()
+            },
+        )
+        .expect("commit_known");
+
+    for i in 0..keys.len() {
+        let value = replica
+            .get(Context::background(), keys[i].as_slice())
+            .expect("get");
+        if i % 2 == 0 {
+            assert_eq!(None, value);
+        } else {
+            assert_eq!(Some(values[i].clone()), value);
+        }
+    }
     assert_eq!(
-        128,
-        tree.cache.borrow().stats().internal_node_count,
-        "cache.internal_node_count"
+        Some(b"bar".to_vec()),
+        replica.get(Context::background(), b"foo").expect("get")
     );
+
+    // Replayed write logs are not subject to the configured size limits.
+    let mut limited = Tree::make()
+        .with_root_type(RootType::State)
+        .with_max_value_size(1)
+        .new(Box::new(NoopReadSyncer));
+    assert!(matches!(
+        limited
+            .apply(Context::background(), &write_log_1)
+            .unwrap_err()
+            .downcast_ref::<TreeError>(),
+        Some(TreeError::ValueTooLarge { .. })
+    ));
+    limited
+        .apply_write_log(Context::background(), &write_log_1)
+        .expect("apply_write_log");
+    limited
+        .commit_known(
+            Context::background(),
+            Root {
+                version: 1,
+                root_type: RootType::State,
+                hash: hash_1,
+                ..Default::default()
+            },
+        )
+        .expect("commit_known");
+
+    // Keys beyond the structural limit are still rejected.
+    let result = limited.apply_write_log(
+        Context::background(),
+        &vec![LogEntry::new(&vec![0; MAX_KEY_SIZE + 1], b"value")],
+    );
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::KeyTooLarge { .. })
+    ));
+}
+
+#[test]
+fn test_apply_batch() {
+    let (keys, values) = generate_key_value_pairs();
+
+    // Reference tree built with individual operations.
+    let mut tree = Tree::make()
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+    }
+    for i in (0..keys.len()).step_by(3) {
+        tree.remove(Context::background(), keys[i].as_slice())
+            .expect("remove");
+    }
+    tree.insert(Context::background(), keys[1].as_slice(), b"updated")
+        .expect("insert");
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+
+    // The same operations as an unordered batch.
+    let mut ops = Vec::new();
+    for i in (0..keys.len()).rev() {
+        ops.push(LogEntry::new(keys[i].as_slice(), values[i].as_slice()));
+    }
+    for i in (0..keys.len()).step_by(3) {
+        ops.push(LogEntry {
+            key: keys[i].clone(),
+            value: None,
+        });
+    }
+    ops.push(LogEntry::new(keys[1].as_slice(), b"updated"));
+
+    let mut batch_tree = Tree::make()
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+    batch_tree
+        .apply(Context::background(), &ops)
+        .expect("apply");
+    let batch_hash = Tree::commit(
+        &mut batch_tree,
+        Context::background(),
+        Default::default(),
+        0,
+    )
+    .expect("commit");
+    assert_eq!(hash, batch_hash, "batch should result in the same root");
+
     assert_eq!(
-        124,
-        tree.cache.borrow().stats().leaf_value_size,
-        "cache.leaf_value_size"
+        Some(b"updated".to_vec()),
+        batch_tree
+            .get(Context::background(), keys[1].as_slice())
+            .expect("get")
+    );
+    assert_eq!(
+        None,
+        batch_tree
+            .get(Context::background(), keys[0].as_slice())
+            .expect("get")
     );
+
+    // Update half of the keys in a batch applied to the committed tree.
+    for i in (0..keys.len()).step_by(2) {
+        tree.insert(Context::background(), keys[i].as_slice(), b"again")
+            .expect("insert");
+    }
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+
+    let ops: Vec<LogEntry> = (0..keys.len())
+        .step_by(2)
+        .map(|i| LogEntry::new(keys[i].as_slice(), b"again"))
+        .collect();
+    batch_tree
+        .apply(Context::background(), &ops)
+        .expect("apply");
+    let batch_hash = Tree::commit(
+        &mut batch_tree,
+        Context::background(),
+        Default::default(),
+        0,
+    )
+    .expect("commit");
+    assert_eq!(hash, batch_hash, "batch should result in the same root");
+
+    // Removing all keys in a batch should result in an empty tree.
+    let ops: Vec<LogEntry> = keys
+        .iter()
+        .map(|key| LogEntry {
+            key: key.clone(),
+            value: None,
+        })
+        .collect();
+    batch_tree
+        .apply(Context::background(), &ops)
+        .expect("apply");
+    let batch_hash = Tree::commit(
+        &mut batch_tree,
+        Context::background(),
+        Default::default(),
+        0,
+    )
+    .expect("commit");
+    assert_eq!(batch_hash, Hash::empty_hash());
+}
+
+#[test]
+fn test_verify_integrity() {
+    let server = ProtocolServer::new(None);
+
+    let mut tree = Tree::make()
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+
+    let (keys, values) = generate_key_value_pairs();
+    let mut write_log = WriteLog::new();
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+        write_log.push(LogEntry::new(keys[i].as_slice(), values[i].as_slice()));
+    }
+
+    // Verification requires a committed root.
+    assert!(tree.verify_integrity(Context::background()).is_err());
+
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    server.apply(&write_log, hash, Default::default(), 0);
+
+    let mismatches = tree
+        .verify_integrity(Context::background())
+        .expect("verify_integrity");
+    assert!(mismatches.is_empty(), "committed tree should be intact");
+
+    // Nodes that need to be fetched should be verified as well.
+    let remote_tree = Tree::make()
+        .with_root(Root {
+            root_type: RootType::State,
+            hash,
+            ..Default::default()
+        })
+        .new(Box::new(server.read_sync()));
+    let mismatches = remote_tree
+        .verify_integrity(Context::background())
+        .expect("verify_integrity");
+    assert!(mismatches.is_empty(), "remote tree should be intact");
+
+    // Tampered remote nodes are rejected when fetched instead of being reported.
+    let (_, mut proof) = tree
+        .get_with_proof(Context::background(), keys[0].as_slice())
+        .expect("get_with_proof");
+    let entry = proof
+        .entries
+        .iter_mut()
+        .rev()
+        .find_map(|entry| entry.as_mut())
+        .expect("proof should have entries");
+    let last = entry.len() - 1;
+    entry[last] ^= 0xff;
+    let remote_tree = Tree::make()
+        .with_root(Root {
+            root_type: RootType::State,
+            hash,
+            ..Default::default()
+        })
+        .new(Box::new(StaticReadSyncer { proof }));
+    let err = remote_tree
+        .verify_integrity(Context::background())
+        .expect_err("verify_integrity should fail");
+    assert!(matches!(
+        err.downcast_ref::<VerifierError>(),
+        Some(VerifierError::BadRoot { .. })
+    ));
+
+    // Corrupt a leaf in memory.
+    let mut ptr = tree.cache.borrow().get_pending_root();
+    loop {
+        let node_ref = ptr.borrow().get_node().expect("get_node");
+        let next = match *node_ref.borrow() {
+            NodeBox::Internal(ref n) => n.left.clone(),
+            NodeBox::Leaf(_) => break,
+        };
+        ptr = next;
+    }
+    let node_ref = ptr.borrow().get_node().expect("get_node");
+    let corrupted_key = match *node_ref.borrow_mut() {
+        NodeBox::Leaf(ref mut n) => {
+            n.value = b"corrupted".to_vec();
+            n.key.clone()
+        }
+        _ => unreachable!(),
+    };
+
+    let mismatches = tree
+        .verify_integrity(Context::background())
+        .expect("verify_integrity");
+    assert_eq!(1, mismatches.len(), "corrupted leaf should be reported");
+    assert_eq!(corrupted_key, mismatches[0].path);
+    assert_eq!(ptr.borrow().hash, mismatches[0].expected);
+}
+
+#[test]
+fn test_diff() {
+    use std::collections::BTreeMap;
+
+    let (keys, values) = generate_key_value_pairs();
+    let mut old_items = BTreeMap::new();
+    for i in 0..keys.len() {
+        old_items.insert(keys[i].clone(), values[i].clone());
+    }
+
+    // Modify, remove and insert some items.
+    let mut new_items = old_items.clone();
+    for i in (0..keys.len()).step_by(7) {
+        new_items.remove(&keys[i]);
+    }
+    for i in (3..keys.len()).step_by(11) {
+        new_items.insert(keys[i].clone(), b"modified".to_vec());
+    }
+    new_items.insert(b"key".to_vec(), b"new".to_vec());
+    new_items.insert(b"key 1000".to_vec(), b"new".to_vec());
+    new_items.insert(b"new key".to_vec(), b"new".to_vec());
+
+    let make_tree = |items: &BTreeMap<Vec<u8>, Vec<u8>>| -> Tree {
+        let mut tree = Tree::make()
+            .with_root_type(RootType::State)
+            .new(Box::new(NoopReadSyncer));
+        for (key, value) in items {
+            tree.insert(Context::background(), key, value)
+                .expect("insert");
+        }
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+        tree
+    };
+    let old_tree = make_tree(&old_items);
+    let new_tree = make_tree(&new_items);
+
+    let mut expected = Vec::new();
+    let all_keys: BTreeMap<_, _> = old_items
+        .keys()
+        .chain(new_items.keys())
+        .map(|k| (k.clone(), ()))
+        .collect();
+    for key in all_keys.keys() {
+        match (old_items.get(key), new_items.get(key)) {
+            (Some(old_value), Some(new_value)) if old_value != new_value => {
+                expected.push(DiffEntry::Modified {
+                    key: key.clone(),
+                    old_value: old_value.clone(),
+                    new_value: new_value.clone(),
+                })
+            }
+            (Some(value), None) => expected.push(DiffEntry::Deleted {
+                key: key.clone(),
+                value: value.clone(),
+            }),
+            (None, Some(value)) => expected.push(DiffEntry::Inserted {
+                key: key.clone(),
+                value: value.clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    let diff = old_tree
+        .diff(Context::background(), &new_tree)
+        .expect("diff");
+    assert_eq!(expected, diff, "diff should contain all changes");
+
+    // Reverse diff.
+    let reverse_diff = new_tree
+        .diff(Context::background(), &old_tree)
+        .expect("diff");
+    assert_eq!(reverse_diff.len(), diff.len());
+    for (entry, reverse_entry) in diff.iter().zip(reverse_diff.iter()) {
+        assert_eq!(entry.key(), reverse_entry.key());
+    }
+
+    // Identical trees.
+    let diff = old_tree
+        .diff(Context::background(), &make_tree(&old_items))
+        .expect("diff");
+    assert!(
+        diff.is_empty(),
+        "identical trees should have no differences"
+    );
+
+    // Empty tree.
+    let empty_tree = make_tree(&BTreeMap::new());
+    let diff = empty_tree
+        .diff(Context::background(), &new_tree)
+        .expect("diff");
+    assert_eq!(diff.len(), new_items.len());
+    assert!(diff
+        .iter()
+        .all(|entry| matches!(entry, DiffEntry::Inserted { .. })));
+}
+
+#[test]
+fn test_diff_label_change() {
+    let make_tree = |extra: &[&[u8]]| -> Tree {
+        let mut tree = Tree::make()
+            .with_root_type(RootType::State)
+            .new(Box::new(NoopReadSyncer));
+        let (keys, values) = generate_key_value_pairs();
+        for i in 0..keys.len() {
+            tree.insert(
+                Context::background(),
+                keys[i].as_slice(),
+                values[i].as_slice(),
+            )
+            .expect("insert");
+        }
+        for key in extra {
+            tree.insert(Context::background(), key, b"new")
+                .expect("insert");
+        }
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+        tree
+    };
+    // Both keys shorten the label of the root node, pushing all existing keys one or
+    // two levels down.
+    let old_tree = make_tree(&[]);
+    let new_tree = make_tree(&[b"key", b"new key"]);
+
+    let old_hits = old_tree.cache_stats().hits;
+    let new_hits = new_tree.cache_stats().hits;
+    let diff = old_tree
+        .diff(Context::background(), &new_tree)
+        .expect("diff");
+    assert_eq!(
+        diff,
+        vec![
+            DiffEntry::Inserted {
+                key: b"key".to_vec(),
+                value: b"new".to_vec(),
+            },
+            DiffEntry::Inserted {
+                key: b"new key".to_vec(),
+                value: b"new".to_vec(),
+            },
+        ]
+    );
+    // Unchanged subtrees should be skipped instead of being compared entry by entry.
+    assert!(old_tree.cache_stats().hits - old_hits < 20);
+    assert!(new_tree.cache_stats().hits - new_hits < 20);
+
+    // Reverse diff, where the labels get longer.
+    let diff = new_tree
+        .diff(Context::background(), &old_tree)
+        .expect("diff");
+    assert_eq!(
+        diff,
+        vec![
+            DiffEntry::Deleted {
+                key: b"key".to_vec(),
+                value: b"new".to_vec(),
+            },
+            DiffEntry::Deleted {
+                key: b"new key".to_vec(),
+                value: b"new".to_vec(),
+            },
+        ]
+    );
+
+    // Labels that change in both trees.
+    let other_tree = make_tree(&[b"key", b"key 1000"]);
+    let diff = new_tree
+        .diff(Context::background(), &other_tree)
+        .expect("diff");
+    assert_eq!(
+        diff,
+        vec![
+            DiffEntry::Inserted {
+                key: b"key 1000".to_vec(),
+                value: b"new".to_vec(),
+            },
+            DiffEntry::Deleted {
+                key: b"new key".to_vec(),
+                value: b"new".to_vec(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_cache_stats() {
+    let server = ProtocolServer::new(None);
+
+    let mut tree = Tree::make()
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+
+    let (keys, values) = generate_key_value_pairs_ex("".to_string(), 100);
+    let mut write_log = WriteLog::new();
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+        write_log.push(LogEntry::new(keys[i].as_slice(), values[i].as_slice()));
+    }
+
+    // Uncommitted nodes are not tracked by the cache, so lookups do not count as hits.
+    for i in 0..keys.len() {
+        tree.get(Context::background(), keys[i].as_slice())
+            .expect("get");
+    }
+    assert_eq!(0, tree.cache_stats().hits, "cache.hits");
+
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    server.apply(&write_log, hash, Default::default(), 0);
+
+    // Once committed, the same lookups are served from the cache.
+    for i in 0..keys.len() {
+        tree.get(Context::background(), keys[i].as_slice())
+            .expect("get");
+    }
+    assert!(tree.cache_stats().hits > 0, "cache.hits should be non-zero");
+
+    let remote_tree = Tree::make()
+        .with_root(Root {
+            root_type: RootType::State,
+            hash,
+            ..Default::default()
+        })
+        .new(Box::new(server.read_sync()));
+
+    let stats = remote_tree.cache_stats();
+    assert_eq!(0, stats.hits, "cache.hits");
+    assert_eq!(0, stats.misses, "cache.misses");
+
+    // The first lookup of each key must go to the read syncer.
+    for i in 0..keys.len() {
+        remote_tree
+            .get(Context::background(), keys[i].as_slice())
+            .expect("get");
+    }
+    let stats = remote_tree.cache_stats();
+    assert!(stats.misses > 0, "cache.misses should be non-zero");
+    assert_eq!(0, stats.evictions, "cache.evictions");
+
+    // Repeated lookups must be served from the cache.
+    for i in 0..keys.len() {
+        remote_tree
+            .get(Context::background(), keys[i].as_slice())
+            .expect("get");
+    }
+    let new_stats = remote_tree.cache_stats();
+    assert_eq!(stats.misses, new_stats.misses, "cache.misses");
+    assert!(new_stats.hits > stats.hits, "cache.hits should increase");
+}
+
+#[test]
+fn test_size_limits() {
+    let mut tree = Tree::make()
+        .with_max_key_size(8)
+        .with_max_value_size(16)
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+
+    tree.insert(Context::background(), b"key 1", b"value 1")
+        .expect("insert");
+    tree.insert(Context::background(), b"key 2345", b"0123456789abcdef")
+        .expect("insert at the limits");
+
+    let result = tree.insert(Context::background(), b"key 23456", b"value");
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::KeyTooLarge { size: 9, max: 8 })
+    ));
+    let result = tree.insert(Context::background(), b"key 3", b"0123456789abcdefg");
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::ValueTooLarge { size: 17, max: 16 })
+    ));
+
+    // Rejected inserts must not modify the tree.
+    assert_eq!(
+        None,
+        tree.get(Context::background(), b"key 3").expect("get")
+    );
+
+    // Inserts through an overlay are checked before they are buffered.
+    let mut overlay = OverlayTree::new(&mut tree);
+    overlay
+        .insert(Context::background(), b"key 4", b"value 4")
+        .expect("insert");
+    let result = overlay.insert(Context::background(), b"key 5", b"0123456789abcdefg");
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::ValueTooLarge { size: 17, max: 16 })
+    ));
+    assert_eq!(
+        None,
+        overlay.get(Context::background(), b"key 5").expect("get")
+    );
+    let write_log = overlay.commit(Context::background()).expect("commit");
+    assert_eq!(
+        write_log,
+        vec![LogEntry::new(b"key 4", b"value 4")],
+        "only the accepted insert should be committed"
+    );
+}
+
+#[test]
+fn test_size_limits_mkvs() {
+    let mut tree = Tree::make()
+        .with_max_value_size(16)
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+    let mut overlay = OverlayTree::new(&mut tree);
+
+    // Transactions only see the state as an MKVS, where an oversized value must be rejected
+    // with an error instead of a panic.
+    let state: &mut dyn MKVS = &mut overlay;
+    state
+        .try_insert(Context::background(), b"key 1", b"value 1")
+        .expect("try_insert");
+    let result = state.try_insert(Context::background(), b"key 2", b"0123456789abcdefg");
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::ValueTooLarge { size: 17, max: 16 })
+    ));
+    assert_eq!(None, state.get(Context::background(), b"key 2"));
+    assert_eq!(
+        Some(b"value 1".to_vec()),
+        state.get(Context::background(), b"key 1")
+    );
+}
+
+#[test]
+fn test_key_length_limit() {
+    let mut tree = Tree::make()
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+    assert_eq!(MAX_KEY_SIZE, tree.key_size_limit());
+
+    // A configured limit cannot exceed what the tree can address.
+    let limited_tree = Tree::make()
+        .with_max_key_size(MAX_KEY_SIZE + 1)
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+    assert_eq!(MAX_KEY_SIZE, limited_tree.key_size_limit());
+
+    tree.insert(Context::background(), b"", b"empty")
+        .expect("insert");
+    let long_key = vec![0x42; MAX_KEY_SIZE];
+    tree.insert(Context::background(), &long_key, b"long")
+        .expect("insert");
+    assert_eq!(
+        Some(b"long".to_vec()),
+        tree.get(Context::background(), &long_key).expect("get")
+    );
+
+    // Length of the key in bits would not fit into Depth and would wrap around.
+    let too_long_key = vec![0x42; MAX_KEY_SIZE + 1];
+    let result = tree.insert(Context::background(), &too_long_key, b"too long");
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::KeyTooLarge { .. })
+    ));
+    let overflowing_key = vec![0x00; MAX_KEY_SIZE + 2];
+    assert!(tree.get(Context::background(), &overflowing_key).is_err());
+    assert!(tree
+        .remove(Context::background(), &overflowing_key)
+        .is_err());
+
+    // Through the infallible interface such keys are simply not present.
+    {
+        let mut overlay = OverlayTree::new(&mut tree);
+        let state: &mut dyn MKVS = &mut overlay;
+        assert_eq!(None, state.get(Context::background(), &overflowing_key));
+        assert_eq!(None, state.remove(Context::background(), &overflowing_key));
+        assert_eq!(
+            Some(b"long".to_vec()),
+            state.get(Context::background(), &long_key)
+        );
+    }
+    assert_eq!(
+        Some(b"empty".to_vec()),
+        tree.get(Context::background(), b"").expect("get")
+    );
+
+    // Seeking to an overflowing key should fail the iterator.
+    {
+        let mut it = tree.iter(Context::background());
+        it.seek(&overflowing_key);
+        assert!(!it.is_valid());
+        assert!(it.error().is_some());
+    }
+
+    // Proofs can not be verified for overflowing keys either.
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    let (_, proof) = tree
+        .get_with_proof(Context::background(), b"")
+        .expect("get_with_proof");
+    let pv = ProofVerifier;
+    let result = pv.verify_proof_for_key(Context::background(), hash, &proof, &overflowing_key);
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<TreeError>(),
+        Some(TreeError::KeyTooLarge { .. })
+    ));
+    let result = pv.verify_proof_for_keys(
+        Context::background(),
+        hash,
+        &proof,
+        &[&b""[..], overflowing_key.as_slice()],
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_value_eviction() {
+    let mut tree = Tree::make()
+        .with_capacity(0, 512)
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+
+    let (keys, values) = generate_key_value_pairs();
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+    }
+    Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+
+    assert_eq!(
+        999,
+        tree.cache.borrow().stats().internal_node_count,
+        "cache.internal_node_count"
+    );
+    // Only a subset of the leaf values should remain in cache. Leaves are
+    // committed in key order and each takes up the size of its key and value.
+    assert_eq!(
+        508,
+        tree.cache.borrow().stats().leaf_value_size,
+        "cache.leaf_value_size"
+    );
+    assert!(
+        tree.cache_stats().evictions > 0,
+        "cache.evictions should be non-zero"
+    );
+}
+
+#[test]
+fn test_node_eviction() {
+    let mut tree = Tree::make()
+        .with_capacity(128, 0)
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+
+    let (keys, values) = generate_key_value_pairs_ex("foo".to_string(), 150);
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+    }
+    Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+
+    let (keys, values) = generate_key_value_pairs_ex("foo key 1".to_string(), 150);
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+    }
+    Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+
+    // Only a subset of nodes should remain in cache.
+    assert_eq!(
+        128,
+        tree.cache.borrow().stats().internal_node_count,
+        "cache.internal_node_count"
+    );
+    let (leaf_count, leaf_size) = cached_leaves(&tree.cache.borrow().get_pending_root());
+    assert_eq!(124, leaf_count, "cached leaf count");
+    assert_eq!(
+        leaf_size,
+        tree.cache.borrow().stats().leaf_value_size,
+        "cache.leaf_value_size"
+    );
+}
+
+#[test]
+fn test_zeroize_values() {
+    let mut tree = Tree::make()
+        .with_capacity(0, 512)
+        .with_zeroize_values(true)
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+
+    let (keys, values) = generate_key_value_pairs();
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+    }
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    assert_eq!(format!("{:?}", hash), ALL_ITEMS_ROOT);
+    assert!(
+        tree.cache_stats().evictions > 0,
+        "cache.evictions should be non-zero"
+    );
+
+    // Values returned by remove must not be affected by zeroization.
+    for i in (0..keys.len()).rev() {
+        if !tree.cache_contains_key(Context::background(), keys[i].as_slice()) {
+            continue;
+        }
+        let old_value = tree
+            .remove(Context::background(), keys[i].as_slice())
+            .expect("remove")
+            .expect("remove_some");
+        assert_eq!(values[i], old_value);
+    }
+
+    // Nodes that are still referenced elsewhere must be left intact.
+    let mut tree = Tree::make()
+        .with_zeroize_values(true)
+        .with_root_type(RootType::State)
+        .new(Box::new(NoopReadSyncer));
+    tree.insert(Context::background(), b"foo", b"bar")
+        .expect("insert");
+    let leaf = tree
+        .cache
+        .borrow()
+        .get_pending_root()
+        .borrow()
+        .get_node()
+        .expect("get_node");
+    tree.remove(Context::background(), b"foo").expect("remove");
+    drop(tree);
+    assert_eq!(noderef_as!(leaf, Leaf).key, b"foo".to_vec());
+    assert_eq!(noderef_as!(leaf, Leaf).value, b"bar".to_vec());
+}
+
+/// Count the leaves that are held in memory and the total size of their keys and values.
+fn cached_leaves(ptr: &NodePtrRef) -> (usize, usize) {
+    match ptr.borrow().node {
+        Some(ref node) => match *node.borrow() {
+            NodeBox::Internal(ref n) => [&n.leaf_node, &n.left, &n.right]
+                .iter()
+                .map(|child| cached_leaves(child))
+                .fold((0, 0), |(count, size), (c, s)| (count + c, size + s)),
+            NodeBox::Leaf(ref n) => (1, n.key.len() + n.value.len()),
+        },
+        None => (0, 0),
+    }
 }
 
 /// Location of the test vectors directory (from Go).
@@ -229,11 +229,16 @@ impl Methods {
         ctx.emit_tag(b"kv_op", b"insert");
         ctx.emit_tag(b"kv_key", args.key.as_bytes());
 
-        let existing = ctx.parent.core.runtime_state.insert(
-            IoContext::create_child(&ctx.parent.core.io_ctx),
-            args.key.as_bytes(),
-            args.value.as_bytes(),
-        );
+        let existing = ctx
+            .parent
+            .core
+            .runtime_state
+            .try_insert(
+                IoContext::create_child(&ctx.parent.core.io_ctx),
+                args.key.as_bytes(),
+                args.value.as_bytes(),
+            )
+            .map_err(|err| err.to_string())?;
         Ok(existing
             .map(|v| String::from_utf8(v))
             .transpose()